@@ -0,0 +1,339 @@
+use std::{collections::HashMap, fmt};
+
+use fxhash::FxBuildHasher;
+
+use crate::{
+    record::{Domain, Record},
+    trc::{
+        hypercall_name, TRC_HVM_HYPERCALL, TRC_HVM_VMENTRY, TRC_HVM_VMEXIT, TRC_HVM_VMEXIT64,
+        TRC_PV_HYPERCALL, TRC_PV_HYPERCALL64, TRC_PV_HYPERCALL_SUBCALL, TRC_PV_HYPERCALL_V2,
+        TRC_PV_HYPERCALL_V2_ARG_MASK,
+    },
+    Trace,
+};
+
+/// Frequency and latency statistics of a hypercall issued by a virtual processor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HypercallEntry {
+    /// The [`Domain`] (and virtual processor) that issued the hypercall.
+    pub(crate) domain: Domain,
+    /// The hypercall number.
+    pub(crate) op: u32,
+    /// The number of times the hypercall was issued.
+    pub(crate) count: u64,
+    /// The number of calls whose duration has been measured.
+    pub(crate) timed: u64,
+    /// The sum of the measured durations (in CPU cycles).
+    pub(crate) total_cycles: u64,
+    /// The shortest measured duration (in CPU cycles), if any.
+    pub(crate) min_cycles: Option<u64>,
+    /// The longest measured duration (in CPU cycles), if any.
+    pub(crate) max_cycles: Option<u64>,
+}
+
+impl HypercallEntry {
+    fn new(domain: Domain, op: u32) -> Self {
+        Self {
+            domain,
+            op,
+            count: 0,
+            timed: 0,
+            total_cycles: 0,
+            min_cycles: None,
+            max_cycles: None,
+        }
+    }
+
+    fn add_duration(&mut self, cycles: u64) {
+        self.timed += 1;
+        self.total_cycles = self.total_cycles.saturating_add(cycles);
+        self.min_cycles = Some(self.min_cycles.map_or(cycles, |min| min.min(cycles)));
+        self.max_cycles = Some(self.max_cycles.map_or(cycles, |max| max.max(cycles)));
+    }
+
+    /// Returns the [`Domain`] (and virtual processor) that issued the hypercall.
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+
+    /// Returns the hypercall number.
+    pub fn op(&self) -> u32 {
+        self.op
+    }
+
+    /// Returns the name of the hypercall, if known.
+    pub fn name(&self) -> Option<&'static str> {
+        hypercall_name(self.op)
+    }
+
+    /// Returns the number of times the hypercall was issued.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the number of calls whose duration has been measured.
+    ///
+    /// **Note:** Only HVM hypercalls are delimited by VMEXIT/VMENTRY events,
+    /// PV hypercalls are counted but never timed.
+    pub fn timed_count(&self) -> u64 {
+        self.timed
+    }
+
+    /// Returns the sum of the measured durations (in CPU cycles).
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    /// Returns the shortest measured duration (in CPU cycles), if any.
+    pub fn min_cycles(&self) -> Option<u64> {
+        self.min_cycles
+    }
+
+    /// Returns the longest measured duration (in CPU cycles), if any.
+    pub fn max_cycles(&self) -> Option<u64> {
+        self.max_cycles
+    }
+
+    /// Returns the average measured duration (in CPU cycles), if any.
+    pub fn avg_cycles(&self) -> Option<u64> {
+        self.total_cycles.checked_div(self.timed)
+    }
+}
+
+/// Report of the hypercalls issued by each virtual processor of a trace.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{analysis::HypercallReport, Result, Trace};
+///
+/// fn main() -> Result<()> {
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///     let report = HypercallReport::from_trace(&trace);
+///     println!("{}", report);
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HypercallReport {
+    pub(crate) entries: Vec<HypercallEntry>,
+}
+
+impl HypercallReport {
+    /// Constructs a `HypercallReport` from the records of a [`Trace`].
+    pub fn from_trace(trace: &Trace) -> Self {
+        let mut analyzer = HypercallAnalyzer::new();
+        trace.iter().for_each(|r| analyzer.push(r));
+        analyzer.finish()
+    }
+
+    /// Returns the entries of the report, sorted by domain, virtual processor and hypercall.
+    pub fn entries(&self) -> &[HypercallEntry] {
+        &self.entries
+    }
+}
+
+impl fmt::Display for HypercallReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn or_dash(value: Option<u64>) -> String {
+            value.map_or_else(|| String::from("-"), |v| v.to_string())
+        }
+
+        writeln!(
+            f,
+            "{:<10} {:>5}  {:<34} {:>10} {:>10} {:>12} {:>12} {:>12}",
+            "domain",
+            "vcpu",
+            "hypercall",
+            "count",
+            "timed",
+            "avg cycles",
+            "min cycles",
+            "max cycles"
+        )?;
+
+        for entry in &self.entries {
+            let hypercall = match entry.name() {
+                Some(name) => format!("{} ({})", name, entry.op),
+                None => format!("unknown ({})", entry.op),
+            };
+
+            writeln!(
+                f,
+                "{:<10} {:>5}  {:<34} {:>10} {:>10} {:>12} {:>12} {:>12}",
+                entry.domain.kind,
+                entry.domain.vcpu,
+                hypercall,
+                entry.count,
+                entry.timed,
+                or_dash(entry.avg_cycles()),
+                or_dash(entry.min_cycles()),
+                or_dash(entry.max_cycles()),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the [`HypercallReport`] of a sequence of records.
+///
+/// Records are expected to be pushed in TSC order for each CPU,
+/// as they are found in a [`Trace`] or read from a trace file.
+#[derive(Debug, Default)]
+pub struct HypercallAnalyzer {
+    cpus: HashMap<u32, CpuState, FxBuildHasher>,
+    entries: HashMap<(Domain, u32), HypercallEntry, FxBuildHasher>,
+}
+
+#[derive(Debug, Default)]
+struct CpuState {
+    exit_tsc: Option<u64>,
+    pending: Option<PendingCall>,
+}
+
+#[derive(Debug)]
+struct PendingCall {
+    domain: Domain,
+    op: u32,
+    start_tsc: u64,
+}
+
+impl HypercallAnalyzer {
+    /// Constructs a new, empty `HypercallAnalyzer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes a single record.
+    pub fn push(&mut self, record: &Record) {
+        let code = record.event.code.value();
+        let tsc = record.event.tsc;
+        let extra = &record.event.extra;
+
+        let state = self.cpus.entry(record.cpu).or_default();
+
+        // The vCPU left the CPU before its hypercall completed
+        if matches!(&state.pending, Some(p) if p.domain != record.domain) {
+            state.pending = None;
+        }
+
+        let op = match code {
+            TRC_HVM_VMEXIT | TRC_HVM_VMEXIT64 => {
+                state.exit_tsc = Some(tsc);
+                state.pending = None;
+                None
+            }
+            TRC_HVM_VMENTRY => {
+                if let Some(call) = state.pending.take() {
+                    self.entries
+                        .entry((call.domain, call.op))
+                        .or_insert_with(|| HypercallEntry::new(call.domain, call.op))
+                        .add_duration(tsc.saturating_sub(call.start_tsc));
+                }
+
+                state.exit_tsc = None;
+                None
+            }
+            TRC_HVM_HYPERCALL => extra[0].map(|op| {
+                state.pending = Some(PendingCall {
+                    domain: record.domain,
+                    op,
+                    start_tsc: state.exit_tsc.unwrap_or(tsc),
+                });
+
+                op
+            }),
+            // The operation follows the (32 or 64-bit) instruction pointer
+            TRC_PV_HYPERCALL | TRC_PV_HYPERCALL64 => extra.iter().rev().find_map(|e| *e),
+            TRC_PV_HYPERCALL_V2 | TRC_PV_HYPERCALL_SUBCALL => {
+                extra[0].map(|op| op & !TRC_PV_HYPERCALL_V2_ARG_MASK)
+            }
+            _ => None,
+        };
+
+        if let Some(op) = op {
+            self.entries
+                .entry((record.domain, op))
+                .or_insert_with(|| HypercallEntry::new(record.domain, op))
+                .count += 1;
+        }
+    }
+
+    /// Consumes the analyzer, returning the collected [`HypercallReport`].
+    pub fn finish(self) -> HypercallReport {
+        let mut entries = self.entries.into_values().collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|e| (u32::from(e.domain), e.op));
+
+        HypercallReport { entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HypercallReport;
+    use crate::{record::Domain, TraceBuilder};
+
+    #[test]
+    fn hvm_duration_test() {
+        let trace = TraceBuilder::new()
+            .schedule(0, Domain::from(0x00050001))
+            .event(100, 0x00081102, &[18])
+            .event(100, 0x00082012, &[24])
+            .event(150, 0x00081001, &[])
+            .event(200, 0x00081102, &[18])
+            .event(200, 0x00082012, &[24])
+            .event(290, 0x00081001, &[])
+            .build()
+            .unwrap();
+
+        let report = HypercallReport::from_trace(&trace);
+        let entry = &report.entries()[0];
+
+        assert_eq!(report.entries().len(), 1);
+        assert_eq!(entry.name(), Some("vcpu_op"));
+        assert_eq!(entry.count(), 2);
+        assert_eq!(entry.timed_count(), 2);
+        assert_eq!(entry.min_cycles(), Some(50));
+        assert_eq!(entry.max_cycles(), Some(90));
+        assert_eq!(entry.avg_cycles(), Some(70));
+    }
+
+    #[test]
+    fn pv_count_test() {
+        let trace = TraceBuilder::new()
+            .schedule(0, Domain::from(0x00050001))
+            .event(100, 0x0020100D, &[0x00300000 | 29])
+            .event(200, 0x0020100D, &[29])
+            .build()
+            .unwrap();
+
+        let report = HypercallReport::from_trace(&trace);
+        let entry = &report.entries()[0];
+
+        assert_eq!(entry.op(), 29);
+        assert_eq!(entry.count(), 2);
+        assert_eq!(entry.timed_count(), 0);
+        assert_eq!(entry.avg_cycles(), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_untimed_test() {
+        let trace = TraceBuilder::new()
+            .event(100, 0x0020100D, &[29])
+            .build()
+            .unwrap();
+
+        let report = HypercallReport::from_trace(&trace);
+        let json = serde_json::to_value(&report).unwrap();
+        let entry = &json["entries"][0];
+
+        assert_eq!(entry["op"], 29);
+        assert_eq!(entry["timed"], 0);
+        assert!(entry["min_cycles"].is_null());
+        assert!(entry["max_cycles"].is_null());
+    }
+}
@@ -0,0 +1,268 @@
+use std::{collections::HashMap, fmt};
+
+use fxhash::FxBuildHasher;
+
+use crate::{
+    record::{Domain, DomainKind, Record},
+    Trace,
+};
+
+/// A virtual processor that has been scheduled on a different physical CPU than last time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Migration {
+    /// The migrated [`Domain`] (and virtual processor).
+    pub(crate) domain: Domain,
+    /// The physical CPU on which the virtual processor ran last time.
+    pub(crate) from_cpu: u32,
+    /// The physical CPU on which the virtual processor is now running.
+    pub(crate) to_cpu: u32,
+    /// The timestamp of the first record of the virtual processor on the new CPU.
+    pub(crate) tsc: u64,
+}
+
+impl Migration {
+    /// Returns the migrated [`Domain`] (and virtual processor).
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+
+    /// Returns the physical CPU on which the virtual processor ran last time.
+    pub fn from_cpu(&self) -> u32 {
+        self.from_cpu
+    }
+
+    /// Returns the physical CPU on which the virtual processor is now running.
+    pub fn to_cpu(&self) -> u32 {
+        self.to_cpu
+    }
+
+    /// Returns the timestamp of the first record of the virtual processor on the new CPU.
+    pub fn tsc(&self) -> u64 {
+        self.tsc
+    }
+}
+
+/// Migration statistics of a single virtual processor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VcpuMigrations {
+    /// The [`Domain`] (and virtual processor).
+    pub(crate) domain: Domain,
+    /// The number of migrations of the virtual processor.
+    pub(crate) count: u64,
+    /// How many times the virtual processor has been scheduled on each CPU (sorted by CPU).
+    pub(crate) affinity: Vec<(u32, u64)>,
+}
+
+impl VcpuMigrations {
+    /// Returns the [`Domain`] (and virtual processor).
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+
+    /// Returns the number of migrations of the virtual processor.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns how many times the virtual processor has been scheduled
+    /// on each physical CPU, as `(cpu, count)` pairs sorted by CPU.
+    pub fn affinity(&self) -> &[(u32, u64)] {
+        &self.affinity
+    }
+}
+
+/// Report of the virtual processor migrations across the physical CPUs of a trace.
+///
+/// The idle and default domains are not tracked.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{analysis::MigrationReport, Result, Trace};
+///
+/// fn main() -> Result<()> {
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///     let report = MigrationReport::from_trace(&trace);
+///
+///     for migration in report.migrations() {
+///         println!("{:?}", migration);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MigrationReport {
+    migrations: Vec<Migration>,
+    vcpus: Vec<VcpuMigrations>,
+}
+
+impl MigrationReport {
+    /// Constructs a `MigrationReport` from the records of a [`Trace`].
+    pub fn from_trace(trace: &Trace) -> Self {
+        let mut analyzer = MigrationAnalyzer::new();
+        trace.iter().for_each(|r| {
+            analyzer.push(r);
+        });
+        analyzer.finish()
+    }
+
+    /// Returns the migrations, in the order they have been detected.
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// Returns the statistics of each virtual processor, sorted by domain and virtual processor.
+    pub fn vcpus(&self) -> &[VcpuMigrations] {
+        &self.vcpus
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10} {:>5} {:>10}  affinity (cpu:runs)",
+            "domain", "vcpu", "migrations"
+        )?;
+
+        for vcpu in &self.vcpus {
+            write!(
+                f,
+                "{:<10} {:>5} {:>10} ",
+                vcpu.domain.kind, vcpu.domain.vcpu, vcpu.count
+            )?;
+
+            for (cpu, runs) in &vcpu.affinity {
+                write!(f, " {}:{}", cpu, runs)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the [`MigrationReport`] of a sequence of records.
+///
+/// A virtual processor is considered scheduled on a CPU when the domain
+/// of the records of that CPU changes to it. Records are expected to be
+/// pushed in TSC order, as they are found in a [`Trace`].
+#[derive(Debug, Default)]
+pub struct MigrationAnalyzer {
+    cpus: HashMap<u32, Domain, FxBuildHasher>,
+    vcpus: HashMap<Domain, VcpuState, FxBuildHasher>,
+    migrations: Vec<Migration>,
+}
+
+#[derive(Debug, Default)]
+struct VcpuState {
+    last_cpu: Option<u32>,
+    count: u64,
+    affinity: HashMap<u32, u64, FxBuildHasher>,
+}
+
+impl MigrationAnalyzer {
+    /// Constructs a new, empty `MigrationAnalyzer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes a single record, returning the [`Migration`] it reveals, if any.
+    pub fn push(&mut self, record: &Record) -> Option<Migration> {
+        let cpu = record.cpu;
+        let domain = record.domain;
+
+        if self.cpus.insert(cpu, domain) == Some(domain) {
+            return None;
+        }
+
+        if matches!(domain.kind, DomainKind::Idle | DomainKind::Default) {
+            return None;
+        }
+
+        let state = self.vcpus.entry(domain).or_default();
+        *state.affinity.entry(cpu).or_default() += 1;
+
+        let from_cpu = state.last_cpu.replace(cpu).filter(|&c| c != cpu)?;
+        state.count += 1;
+
+        let migration = Migration {
+            domain,
+            from_cpu,
+            to_cpu: cpu,
+            tsc: record.event.tsc,
+        };
+
+        self.migrations.push(migration);
+        Some(migration)
+    }
+
+    /// Consumes the analyzer, returning the collected [`MigrationReport`].
+    pub fn finish(self) -> MigrationReport {
+        let mut vcpus = self
+            .vcpus
+            .into_iter()
+            .map(|(domain, state)| {
+                let mut affinity = state.affinity.into_iter().collect::<Vec<_>>();
+                affinity.sort_unstable();
+
+                VcpuMigrations {
+                    domain,
+                    count: state.count,
+                    affinity,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        vcpus.sort_unstable_by_key(|v| u32::from(v.domain));
+
+        MigrationReport {
+            migrations: self.migrations,
+            vcpus,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MigrationAnalyzer;
+    use crate::{
+        record::{Domain, DomainKind},
+        TraceBuilder,
+    };
+
+    #[test]
+    fn migration_test() {
+        let trace = TraceBuilder::new()
+            .schedule(10, Domain::new(DomainKind::Guest(5), 1))
+            .event(20, 0x00081001, &[])
+            .schedule(30, Domain::new(DomainKind::Idle, 0))
+            .cpu(2)
+            .schedule(40, Domain::new(DomainKind::Guest(5), 1))
+            .build()
+            .unwrap();
+
+        let mut analyzer = MigrationAnalyzer::new();
+        let migrations = trace
+            .iter()
+            .filter_map(|r| analyzer.push(r))
+            .collect::<Vec<_>>();
+
+        assert_eq!(migrations.len(), 1);
+        assert_eq!(migrations[0].from_cpu(), 0);
+        assert_eq!(migrations[0].to_cpu(), 2);
+        assert_eq!(migrations[0].tsc(), 40);
+
+        let report = analyzer.finish();
+        let vcpu = &report.vcpus()[0];
+
+        assert_eq!(report.vcpus().len(), 1);
+        assert_eq!(vcpu.count(), 1);
+        assert_eq!(vcpu.affinity(), &[(0, 1), (2, 1)]);
+    }
+}
@@ -0,0 +1,20 @@
+//! Analyses computed from the [`Record`](crate::record::Record)s of a trace.
+//!
+//! Each analysis is driven by an analyzer that is fed one record at a time
+//! (in TSC order for each CPU) and is then turned into a report.
+
+mod hypercall;
+mod migration;
+mod occupancy;
+mod rate;
+mod summary;
+
+pub use self::{
+    hypercall::{HypercallAnalyzer, HypercallEntry, HypercallReport},
+    migration::{Migration, MigrationAnalyzer, MigrationReport, VcpuMigrations},
+    occupancy::{CpuOccupancy, Occupancy, OccupancyAnalyzer, OccupancyInterval},
+    rate::{RateAnalyzer, RateSeries, RateWindow},
+    summary::{
+        DomainSummary, ExitStats, RunstateTimes, SummaryAnalyzer, SummaryReport, VcpuSummary,
+    },
+};
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use fxhash::FxBuildHasher;
+
+use crate::{
+    record::{Domain, DomainKind, Record},
+    Trace,
+};
+
+/// A time interval during which a physical CPU has been occupied by a [`Domain`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OccupancyInterval {
+    /// The timestamp at which the interval starts.
+    pub(crate) start_tsc: u64,
+    /// The timestamp at which the interval ends.
+    pub(crate) end_tsc: u64,
+    /// The [`Domain`] (and virtual processor) occupying the CPU.
+    pub(crate) domain: Domain,
+}
+
+impl OccupancyInterval {
+    /// Returns the timestamp at which the interval starts.
+    pub fn start_tsc(&self) -> u64 {
+        self.start_tsc
+    }
+
+    /// Returns the timestamp at which the interval ends.
+    pub fn end_tsc(&self) -> u64 {
+        self.end_tsc
+    }
+
+    /// Returns the duration of the interval (in CPU cycles).
+    pub fn duration(&self) -> u64 {
+        self.end_tsc - self.start_tsc
+    }
+
+    /// Returns the [`Domain`] (and virtual processor) occupying the CPU.
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+
+    /// Returns `true` if the CPU was running the idle domain.
+    pub fn is_idle(&self) -> bool {
+        self.domain.kind == DomainKind::Idle
+    }
+}
+
+/// The occupancy intervals of a single physical CPU.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CpuOccupancy {
+    /// The processor id (of the host).
+    pub(crate) cpu: u32,
+    /// The occupancy intervals (sorted by time, contiguous).
+    pub(crate) intervals: Vec<OccupancyInterval>,
+}
+
+impl CpuOccupancy {
+    /// Returns the processor id (of the host).
+    pub fn cpu(&self) -> u32 {
+        self.cpu
+    }
+
+    /// Returns the occupancy intervals, sorted by time.
+    ///
+    /// Each interval ends where the next one starts, the
+    /// last one ends at the last record of the CPU.
+    pub fn intervals(&self) -> &[OccupancyInterval] {
+        &self.intervals
+    }
+
+    /// Returns the time (in CPU cycles) spent running domains other than the idle one.
+    ///
+    /// The interval of the [default](DomainKind::Default) domain, preceding the
+    /// first scheduling event of the CPU, is not counted as its domain is unknown.
+    pub fn busy_cycles(&self) -> u64 {
+        self.intervals
+            .iter()
+            .filter(|i| !matches!(i.domain.kind, DomainKind::Idle | DomainKind::Default))
+            .map(OccupancyInterval::duration)
+            .sum()
+    }
+}
+
+/// Physical CPU utilization timeline of a trace.
+///
+/// Intervals are delimited by the changes of the domain tracked for
+/// each CPU while parsing, so records preceding the first scheduling
+/// event of a CPU belong to the [default](DomainKind::Default) domain.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{analysis::Occupancy, Result, Trace};
+///
+/// fn main() -> Result<()> {
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///     let occupancy = Occupancy::from_trace(&trace);
+///
+///     for cpu in occupancy.cpus() {
+///         for interval in cpu.intervals().iter().filter(|i| !i.is_idle()) {
+///             println!("cpu{} {:?}", cpu.cpu(), interval);
+///         }
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Occupancy {
+    cpus: Vec<CpuOccupancy>,
+}
+
+impl Occupancy {
+    /// Constructs an `Occupancy` from the records of a [`Trace`].
+    pub fn from_trace(trace: &Trace) -> Self {
+        let mut analyzer = OccupancyAnalyzer::new();
+        trace.iter().for_each(|r| {
+            analyzer.push(r);
+        });
+        analyzer.finish()
+    }
+
+    /// Returns the occupancy of each CPU, sorted by CPU.
+    pub fn cpus(&self) -> &[CpuOccupancy] {
+        &self.cpus
+    }
+
+    /// Returns the occupancy of the given CPU, if found in the trace.
+    pub fn cpu(&self, cpu: u32) -> Option<&CpuOccupancy> {
+        self.cpus
+            .binary_search_by_key(&cpu, |c| c.cpu)
+            .ok()
+            .map(|i| &self.cpus[i])
+    }
+}
+
+/// Collects the [`Occupancy`] of a sequence of records.
+///
+/// Records are expected to be pushed in TSC order for each CPU,
+/// as they are found in a [`Trace`] or read from a trace file.
+#[derive(Debug, Default)]
+pub struct OccupancyAnalyzer {
+    cpus: HashMap<u32, Vec<OccupancyInterval>, FxBuildHasher>,
+}
+
+impl OccupancyAnalyzer {
+    /// Constructs a new, empty `OccupancyAnalyzer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes a single record, returning the interval it closes, if any.
+    pub fn push(&mut self, record: &Record) -> Option<OccupancyInterval> {
+        let tsc = record.event.tsc;
+        let intervals = self.cpus.entry(record.cpu).or_default();
+
+        match intervals.last_mut() {
+            Some(last) if last.domain == record.domain => {
+                last.end_tsc = last.end_tsc.max(tsc);
+                None
+            }
+            last => {
+                let closed = last.map(|last| {
+                    last.end_tsc = last.end_tsc.max(tsc);
+                    *last
+                });
+
+                intervals.push(OccupancyInterval {
+                    start_tsc: tsc,
+                    end_tsc: tsc,
+                    domain: record.domain,
+                });
+
+                closed
+            }
+        }
+    }
+
+    /// Consumes the analyzer, returning the collected [`Occupancy`].
+    pub fn finish(self) -> Occupancy {
+        let mut cpus = self
+            .cpus
+            .into_iter()
+            .map(|(cpu, intervals)| CpuOccupancy { cpu, intervals })
+            .collect::<Vec<_>>();
+
+        cpus.sort_unstable_by_key(|c| c.cpu);
+
+        Occupancy { cpus }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Occupancy, OccupancyAnalyzer};
+    use crate::{
+        record::{Domain, DomainKind},
+        TraceBuilder,
+    };
+
+    #[test]
+    fn intervals_test() {
+        let trace = TraceBuilder::new()
+            .schedule(10, Domain::new(DomainKind::Guest(5), 1))
+            .event(20, 0x00081001, &[])
+            .schedule(30, Domain::new(DomainKind::Idle, 0))
+            .event(70, 0x00081001, &[])
+            .cpu(1)
+            .schedule(25, Domain::new(DomainKind::Guest(5), 0))
+            .build()
+            .unwrap();
+
+        let mut analyzer = OccupancyAnalyzer::new();
+        let closed = trace
+            .iter()
+            .filter_map(|r| analyzer.push(r))
+            .collect::<Vec<_>>();
+
+        assert_eq!(closed.len(), 1);
+        assert_eq!((closed[0].start_tsc(), closed[0].end_tsc()), (10, 30));
+
+        let occupancy = analyzer.finish();
+        let cpu0 = occupancy.cpu(0).unwrap();
+
+        assert_eq!(occupancy.cpus().len(), 2);
+        assert_eq!(cpu0.intervals().len(), 2);
+        assert!(cpu0.intervals()[1].is_idle());
+        assert_eq!(cpu0.intervals()[1].duration(), 40);
+        assert_eq!(cpu0.busy_cycles(), 20);
+    }
+
+    #[test]
+    fn busy_cycles_test() {
+        let trace = TraceBuilder::new()
+            .event(0, 0x00081001, &[])
+            .schedule(10, Domain::new(DomainKind::Guest(5), 1))
+            .schedule(30, Domain::new(DomainKind::Idle, 0))
+            .event(70, 0x00081001, &[])
+            .build()
+            .unwrap();
+
+        let occupancy = Occupancy::from_trace(&trace);
+        let cpu0 = occupancy.cpu(0).unwrap();
+
+        // The records before the first scheduling event are not busy
+        assert_eq!(cpu0.intervals()[0].domain().kind, DomainKind::Default);
+        assert_eq!(cpu0.busy_cycles(), 20);
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    io,
+};
+
+use fxhash::FxBuildHasher;
+
+use crate::{
+    record::{DomainKind, EventCode, Record},
+    RecordReader, Result, Trace,
+};
+
+/// Event counts of a single time window of a [`RateSeries`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RateWindow {
+    /// The timestamp at which the window starts (inclusive).
+    pub(crate) start_tsc: u64,
+    /// The timestamp at which the window ends (exclusive).
+    pub(crate) end_tsc: u64,
+    /// The number of events in the window.
+    pub(crate) total: u64,
+    /// The number of events of each class (sorted by class).
+    pub(crate) by_class: Vec<(u32, u64)>,
+    /// The number of events of each code (sorted by code).
+    pub(crate) by_code: Vec<(EventCode, u64)>,
+    /// The number of events of each CPU (sorted by CPU).
+    pub(crate) by_cpu: Vec<(u32, u64)>,
+    /// The number of events of each domain (sorted by domain id).
+    pub(crate) by_domain: Vec<(DomainKind, u64)>,
+}
+
+impl RateWindow {
+    /// Returns the timestamp at which the window starts (inclusive).
+    pub fn start_tsc(&self) -> u64 {
+        self.start_tsc
+    }
+
+    /// Returns the timestamp at which the window ends (exclusive).
+    pub fn end_tsc(&self) -> u64 {
+        self.end_tsc
+    }
+
+    /// Returns the number of events in the window.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the number of events of each class (see [`EventCode::main`]),
+    /// as `(class, count)` pairs sorted by class.
+    pub fn by_class(&self) -> &[(u32, u64)] {
+        &self.by_class
+    }
+
+    /// Returns the number of events of each code, as `(code, count)` pairs sorted by code.
+    pub fn by_code(&self) -> &[(EventCode, u64)] {
+        &self.by_code
+    }
+
+    /// Returns the number of events of each CPU, as `(cpu, count)` pairs sorted by CPU.
+    pub fn by_cpu(&self) -> &[(u32, u64)] {
+        &self.by_cpu
+    }
+
+    /// Returns the number of events of each domain, as `(domain, count)` pairs sorted by domain id.
+    pub fn by_domain(&self) -> &[(DomainKind, u64)] {
+        &self.by_domain
+    }
+}
+
+/// Event rates of a trace, bucketed into fixed time windows.
+///
+/// Windows are aligned to multiples of their width (in CPU cycles),
+/// so that series computed from different sources can be compared.
+/// Only the windows with events are kept, see [`dense_windows`](RateSeries::dense_windows)
+/// to include the empty ones.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{analysis::RateSeries, RecordReader, Result};
+///
+/// fn main() -> Result<()> {
+///     let reader = RecordReader::from_file("/path/to/xentrace.bin")?;
+///     let series = RateSeries::from_reader(reader, 1_000_000)?;
+///
+///     for window in series.windows() {
+///         println!("{} {}", window.start_tsc(), window.total());
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RateSeries {
+    width: u64,
+    windows: Vec<RateWindow>,
+}
+
+impl RateSeries {
+    /// Constructs a `RateSeries` from the records of a [`Trace`],
+    /// using windows `width` CPU cycles wide.
+    pub fn from_trace(trace: &Trace, width: u64) -> Self {
+        let mut analyzer = RateAnalyzer::new(width);
+        trace.iter().for_each(|r| analyzer.push(r));
+        analyzer.finish()
+    }
+
+    /// Constructs a `RateSeries` from the records of a [`RecordReader`],
+    /// using windows `width` CPU cycles wide.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to read a record.
+    pub fn from_reader<R: io::Read>(reader: RecordReader<R>, width: u64) -> Result<Self> {
+        let mut analyzer = RateAnalyzer::new(width);
+
+        for record in reader {
+            analyzer.push(&record?);
+        }
+
+        Ok(analyzer.finish())
+    }
+
+    /// Returns the width of the windows (in CPU cycles).
+    pub fn width(&self) -> u64 {
+        self.width
+    }
+
+    /// Returns the windows of the series with at least one event, sorted by time.
+    pub fn windows(&self) -> &[RateWindow] {
+        &self.windows
+    }
+
+    /// Returns an iterator over all the windows of the series, from the first
+    /// to the last one with events, sorted by time.
+    ///
+    /// Windows without events are yielded with zero counts. Since a stray
+    /// timestamp can make the series span a huge number of windows, they
+    /// are created lazily: the caller decides how many of them to collect.
+    pub fn dense_windows(&self) -> impl Iterator<Item = RateWindow> + '_ {
+        let width = self.width;
+        let first = self.windows.first().map_or(0, |w| w.start_tsc / width);
+        let last = self.windows.last().map_or(0, |w| w.start_tsc / width + 1);
+        let mut windows = self.windows.iter().peekable();
+
+        (first..last).map(move |index| match windows.peek() {
+            Some(window) if window.start_tsc / width == index => windows.next().unwrap().clone(),
+            _ => RateWindow {
+                start_tsc: index * width,
+                end_tsc: (index + 1).saturating_mul(width),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// Collects the [`RateSeries`] of a sequence of records.
+///
+/// Records can be pushed in any order.
+#[derive(Debug)]
+pub struct RateAnalyzer {
+    width: u64,
+    windows: BTreeMap<u64, WindowCounts>,
+}
+
+#[derive(Debug, Default)]
+struct WindowCounts {
+    total: u64,
+    by_class: HashMap<u32, u64, FxBuildHasher>,
+    by_code: HashMap<EventCode, u64, FxBuildHasher>,
+    by_cpu: HashMap<u32, u64, FxBuildHasher>,
+    by_domain: HashMap<DomainKind, u64, FxBuildHasher>,
+}
+
+impl RateAnalyzer {
+    /// Constructs a new, empty `RateAnalyzer` using windows `width` CPU cycles wide.
+    ///
+    /// **Note:** A width of zero is treated as one.
+    pub fn new(width: u64) -> Self {
+        Self {
+            width: width.max(1),
+            windows: BTreeMap::new(),
+        }
+    }
+
+    /// Processes a single record.
+    pub fn push(&mut self, record: &Record) {
+        let code = record.event.code;
+        let index = record.event.tsc / self.width;
+        let counts = self.windows.entry(index).or_default();
+
+        counts.total += 1;
+        *counts.by_class.entry(code.main()).or_default() += 1;
+        *counts.by_code.entry(code).or_default() += 1;
+        *counts.by_cpu.entry(record.cpu).or_default() += 1;
+        *counts.by_domain.entry(record.domain.kind).or_default() += 1;
+    }
+
+    /// Consumes the analyzer, returning the collected [`RateSeries`].
+    pub fn finish(self) -> RateSeries {
+        fn sorted<K: Eq + Hash, O: Ord>(
+            map: HashMap<K, u64, FxBuildHasher>,
+            key: impl Fn(&K) -> O,
+        ) -> Vec<(K, u64)> {
+            let mut vec = map.into_iter().collect::<Vec<_>>();
+            vec.sort_unstable_by_key(|(k, _)| key(k));
+            vec
+        }
+
+        let width = self.width;
+        let mut windows = Vec::with_capacity(self.windows.len());
+
+        for (index, counts) in self.windows {
+            windows.push(RateWindow {
+                start_tsc: index * width,
+                end_tsc: (index + 1).saturating_mul(width),
+                total: counts.total,
+                by_class: sorted(counts.by_class, |c| *c),
+                by_code: sorted(counts.by_code, |c| c.value()),
+                by_cpu: sorted(counts.by_cpu, |c| *c),
+                by_domain: sorted(counts.by_domain, |d| u16::from(d)),
+            });
+        }
+
+        RateSeries { width, windows }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateSeries;
+    use crate::{
+        record::{Domain, DomainKind, EventCode},
+        TraceBuilder,
+    };
+
+    #[test]
+    fn window_test() {
+        let dom5 = Domain::new(DomainKind::Guest(5), 1);
+        let trace = TraceBuilder::new()
+            .schedule(100, dom5)
+            .event(150, 0x00081001, &[])
+            .event(310, 0x00081001, &[])
+            .cpu(1)
+            .schedule(100, dom5)
+            .event(120, 0x00081002, &[])
+            .build()
+            .unwrap();
+
+        let series = RateSeries::from_trace(&trace, 100);
+        let windows = series.windows();
+
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].start_tsc(), 100);
+        assert_eq!(windows[0].total(), 4);
+        assert_eq!(windows[0].by_cpu(), &[(0, 2), (1, 2)]);
+        assert_eq!(windows[0].by_class(), &[(0x0002, 2), (0x0008, 2)]);
+        assert_eq!(windows[0].by_domain(), &[(DomainKind::Guest(5), 4)]);
+        assert_eq!(windows[1].end_tsc(), 400);
+        assert_eq!(windows[1].by_code(), &[(EventCode::from(0x00081001), 1)]);
+
+        let dense = series.dense_windows().collect::<Vec<_>>();
+        assert_eq!(dense.len(), 3);
+        assert_eq!(dense[0], windows[0]);
+        assert_eq!((dense[1].start_tsc(), dense[1].total()), (200, 0));
+        assert_eq!(dense[2], windows[1]);
+    }
+
+    #[test]
+    fn stray_tsc_test() {
+        let trace = TraceBuilder::new()
+            .event(0, 0x00081001, &[])
+            .event(1_000_000_000_000_000, 0x00081001, &[])
+            .build()
+            .unwrap();
+
+        let series = RateSeries::from_trace(&trace, 1000);
+        assert_eq!(series.windows().len(), 2);
+        assert_eq!(series.dense_windows().take(3).count(), 3);
+    }
+}
@@ -0,0 +1,548 @@
+use std::{collections::HashMap, fmt};
+
+use fxhash::FxBuildHasher;
+
+use super::{HypercallAnalyzer, HypercallEntry};
+use crate::{
+    record::{Domain, DomainKind, Record},
+    trc::{
+        vmx_exit_reason_name, TRC_HVM_VMENTRY, TRC_HVM_VMEXIT, TRC_HVM_VMEXIT64, TRC_LOST_RECORDS,
+        TRC_SCHED_RUNSTATE_CHANGE, TRC_SCHED_RUNSTATE_MASK,
+    },
+    Trace,
+};
+
+/// Time (in CPU cycles) spent by a virtual processor in each runstate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RunstateTimes {
+    /// Time spent running on a physical CPU.
+    pub(crate) running: u64,
+    /// Time spent waiting for a physical CPU.
+    pub(crate) runnable: u64,
+    /// Time spent blocked (*e.g.* waiting for an event).
+    pub(crate) blocked: u64,
+    /// Time spent offline.
+    pub(crate) offline: u64,
+}
+
+impl RunstateTimes {
+    /// Returns the time spent running on a physical CPU.
+    pub fn running(&self) -> u64 {
+        self.running
+    }
+
+    /// Returns the time spent waiting for a physical CPU.
+    pub fn runnable(&self) -> u64 {
+        self.runnable
+    }
+
+    /// Returns the time spent blocked (*e.g.* waiting for an event).
+    pub fn blocked(&self) -> u64 {
+        self.blocked
+    }
+
+    /// Returns the time spent offline.
+    pub fn offline(&self) -> u64 {
+        self.offline
+    }
+
+    fn add(&mut self, state: u32, cycles: u64) {
+        let time = match state {
+            0 => &mut self.running,
+            1 => &mut self.runnable,
+            2 => &mut self.blocked,
+            3 => &mut self.offline,
+            _ => return,
+        };
+
+        *time = time.saturating_add(cycles);
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.running = self.running.saturating_add(other.running);
+        self.runnable = self.runnable.saturating_add(other.runnable);
+        self.blocked = self.blocked.saturating_add(other.blocked);
+        self.offline = self.offline.saturating_add(other.offline);
+    }
+}
+
+/// Statistics of the VMEXITs of a virtual processor with the same exit reason.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ExitStats {
+    /// The exit reason.
+    pub(crate) reason: u32,
+    /// The number of exits.
+    pub(crate) count: u64,
+    /// The number of exits whose duration (up to the next VMENTRY) has been measured.
+    pub(crate) timed: u64,
+    /// The sum of the measured durations (in CPU cycles).
+    pub(crate) total_cycles: u64,
+}
+
+impl ExitStats {
+    /// Returns the exit reason.
+    pub fn reason(&self) -> u32 {
+        self.reason
+    }
+
+    /// Returns the name of the (VMX) exit reason, if known.
+    pub fn name(&self) -> Option<&'static str> {
+        vmx_exit_reason_name(self.reason)
+    }
+
+    /// Returns the number of exits.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the number of exits whose duration (up to the next VMENTRY) has been measured.
+    pub fn timed_count(&self) -> u64 {
+        self.timed
+    }
+
+    /// Returns the sum of the measured durations (in CPU cycles).
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    /// Returns the average measured duration (in CPU cycles), if any.
+    pub fn avg_cycles(&self) -> Option<u64> {
+        self.total_cycles.checked_div(self.timed)
+    }
+}
+
+/// Summary of a single virtual processor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VcpuSummary {
+    /// The [`Domain`] (and virtual processor).
+    pub(crate) domain: Domain,
+    /// The time spent in each runstate.
+    pub(crate) runstates: RunstateTimes,
+    /// The VMEXITs, by exit reason (sorted by reason).
+    pub(crate) exits: Vec<ExitStats>,
+    /// The hypercalls (sorted by hypercall number).
+    pub(crate) hypercalls: Vec<HypercallEntry>,
+}
+
+impl VcpuSummary {
+    fn new(domain: Domain) -> Self {
+        Self {
+            domain,
+            runstates: RunstateTimes::default(),
+            exits: Vec::new(),
+            hypercalls: Vec::new(),
+        }
+    }
+
+    /// Returns the [`Domain`] (and virtual processor).
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+
+    /// Returns the time spent in each runstate.
+    pub fn runstates(&self) -> &RunstateTimes {
+        &self.runstates
+    }
+
+    /// Returns the VMEXITs, by exit reason (sorted by reason).
+    pub fn exits(&self) -> &[ExitStats] {
+        &self.exits
+    }
+
+    /// Returns the hypercalls (sorted by hypercall number).
+    pub fn hypercalls(&self) -> &[HypercallEntry] {
+        &self.hypercalls
+    }
+}
+
+/// Summary of a single domain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DomainSummary {
+    /// The [type](DomainKind) of virtual machine.
+    pub(crate) kind: DomainKind,
+    /// The time spent in each runstate by all the virtual processors of the domain.
+    pub(crate) runstates: RunstateTimes,
+    /// The summaries of the virtual processors (sorted by virtual processor).
+    pub(crate) vcpus: Vec<VcpuSummary>,
+}
+
+impl DomainSummary {
+    /// Returns the [type](DomainKind) of virtual machine.
+    pub fn kind(&self) -> DomainKind {
+        self.kind
+    }
+
+    /// Returns the time spent in each runstate by all the virtual processors of the domain.
+    pub fn runstates(&self) -> &RunstateTimes {
+        &self.runstates
+    }
+
+    /// Returns the summaries of the virtual processors (sorted by virtual processor).
+    pub fn vcpus(&self) -> &[VcpuSummary] {
+        &self.vcpus
+    }
+}
+
+/// Per-domain and per-vCPU summary of a trace, in the spirit of `xenalyze --summary`.
+///
+/// The report can be rendered as plain text through its `Display` implementation.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{analysis::SummaryReport, Result, Trace};
+///
+/// fn main() -> Result<()> {
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///     let summary = SummaryReport::from_trace(&trace);
+///     print!("{}", summary);
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SummaryReport {
+    first_tsc: u64,
+    last_tsc: u64,
+    lost_records: Vec<(u32, u64)>,
+    domains: Vec<DomainSummary>,
+}
+
+impl SummaryReport {
+    /// Constructs a `SummaryReport` from the records of a [`Trace`].
+    pub fn from_trace(trace: &Trace) -> Self {
+        let mut analyzer = SummaryAnalyzer::new();
+        trace.iter().for_each(|r| analyzer.push(r));
+        analyzer.finish()
+    }
+
+    /// Returns the timestamp of the first record.
+    pub fn first_tsc(&self) -> u64 {
+        self.first_tsc
+    }
+
+    /// Returns the timestamp of the last record.
+    pub fn last_tsc(&self) -> u64 {
+        self.last_tsc
+    }
+
+    /// Returns the number of records lost by each CPU, as `(cpu, count)` pairs sorted by CPU.
+    pub fn lost_records(&self) -> &[(u32, u64)] {
+        &self.lost_records
+    }
+
+    /// Returns the summaries of the domains (sorted by domain id).
+    pub fn domains(&self) -> &[DomainSummary] {
+        &self.domains
+    }
+}
+
+impl fmt::Display for SummaryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.last_tsc.saturating_sub(self.first_tsc);
+
+        // Percentages are relative to the trace time of each of the `vcpus`
+        let runstates = |f: &mut fmt::Formatter<'_>, times: &RunstateTimes, vcpus: usize| {
+            let total = total.saturating_mul(vcpus as u64);
+
+            writeln!(f, " Runstates:")?;
+
+            for (name, cycles) in [
+                ("running", times.running),
+                ("runnable", times.runnable),
+                ("blocked", times.blocked),
+                ("offline", times.offline),
+            ] {
+                let percent = match total {
+                    0 => 0.0,
+                    _ => cycles as f64 * 100.0 / total as f64,
+                };
+
+                writeln!(f, "  {:<10} {:>16} cycles {:>7.2}%", name, cycles, percent)?;
+            }
+
+            Ok(())
+        };
+
+        writeln!(f, "Total time: {} cycles", total)?;
+
+        if !self.lost_records.is_empty() {
+            writeln!(f, "Lost records:")?;
+
+            for (cpu, count) in &self.lost_records {
+                writeln!(f, "  cpu{:<4} {:>10}", cpu, count)?;
+            }
+        }
+
+        for domain in &self.domains {
+            writeln!(f, "|-- Domain {} --|", domain.kind)?;
+            runstates(f, &domain.runstates, domain.vcpus.len())?;
+
+            for vcpu in &domain.vcpus {
+                writeln!(f, " -- v{} --", vcpu.domain.vcpu)?;
+                runstates(f, &vcpu.runstates, 1)?;
+
+                if !vcpu.exits.is_empty() {
+                    let count = vcpu.exits.iter().map(|e| e.count).sum::<u64>();
+                    writeln!(f, " Exits: {}", count)?;
+
+                    for exit in &vcpu.exits {
+                        let name = match exit.name() {
+                            Some(name) => format!("{} ({})", name, exit.reason),
+                            None => format!("unknown ({})", exit.reason),
+                        };
+
+                        let avg = exit.avg_cycles().unwrap_or(0);
+                        writeln!(
+                            f,
+                            "  {:<36} {:>10} {:>12} avg cycles",
+                            name, exit.count, avg
+                        )?;
+                    }
+                }
+
+                if !vcpu.hypercalls.is_empty() {
+                    let count = vcpu.hypercalls.iter().map(|h| h.count).sum::<u64>();
+                    writeln!(f, " Hypercalls: {}", count)?;
+
+                    for hypercall in &vcpu.hypercalls {
+                        let name = match hypercall.name() {
+                            Some(name) => format!("{} ({})", name, hypercall.op),
+                            None => format!("unknown ({})", hypercall.op),
+                        };
+
+                        let avg = hypercall.avg_cycles().unwrap_or(0);
+                        writeln!(
+                            f,
+                            "  {:<36} {:>10} {:>12} avg cycles",
+                            name, hypercall.count, avg
+                        )?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the [`SummaryReport`] of a sequence of records.
+///
+/// Records are expected to be pushed in TSC order, as they are found in a [`Trace`].
+#[derive(Debug, Default)]
+pub struct SummaryAnalyzer {
+    first_tsc: Option<u64>,
+    last_tsc: u64,
+    lost_records: HashMap<u32, u64, FxBuildHasher>,
+    runstates: HashMap<Domain, (u32, u64), FxBuildHasher>,
+    exits: HashMap<u32, (Domain, u32, u64), FxBuildHasher>,
+    vcpus: HashMap<Domain, VcpuData, FxBuildHasher>,
+    hypercalls: HypercallAnalyzer,
+}
+
+#[derive(Debug, Default)]
+struct VcpuData {
+    runstates: RunstateTimes,
+    exits: HashMap<u32, ExitStats, FxBuildHasher>,
+}
+
+impl SummaryAnalyzer {
+    /// Constructs a new, empty `SummaryAnalyzer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes a single record.
+    pub fn push(&mut self, record: &Record) {
+        let code = record.event.code.value();
+        let tsc = record.event.tsc;
+
+        self.first_tsc.get_or_insert(tsc);
+        self.last_tsc = self.last_tsc.max(tsc);
+        self.hypercalls.push(record);
+
+        if code & !TRC_SCHED_RUNSTATE_MASK == TRC_SCHED_RUNSTATE_CHANGE {
+            let old = (code >> 8) & 0xF;
+            let new = (code >> 4) & 0xF;
+            let domain = Domain::from(record.event.extra[0].unwrap_or(0));
+
+            // The time spent in the old state is known only after the first change
+            if let Some((_, since)) = self.runstates.insert(domain, (new, tsc)) {
+                self.vcpus
+                    .entry(domain)
+                    .or_default()
+                    .runstates
+                    .add(old, tsc.saturating_sub(since));
+            }
+
+            return;
+        }
+
+        match code {
+            TRC_HVM_VMEXIT | TRC_HVM_VMEXIT64 => {
+                let reason = record.event.extra[0].unwrap_or(0);
+                self.exits.insert(record.cpu, (record.domain, reason, tsc));
+
+                let data = self.vcpus.entry(record.domain).or_default();
+                data.exits
+                    .entry(reason)
+                    .or_insert_with(|| ExitStats {
+                        reason,
+                        ..Default::default()
+                    })
+                    .count += 1;
+            }
+            TRC_HVM_VMENTRY => {
+                let Some((domain, reason, since)) = self.exits.remove(&record.cpu) else {
+                    return;
+                };
+
+                // The vCPU left the CPU before reentering the guest
+                if domain != record.domain {
+                    return;
+                }
+
+                if let Some(exit) = self
+                    .vcpus
+                    .get_mut(&domain)
+                    .and_then(|d| d.exits.get_mut(&reason))
+                {
+                    exit.timed += 1;
+                    exit.total_cycles = exit.total_cycles.saturating_add(tsc.saturating_sub(since));
+                }
+            }
+            TRC_LOST_RECORDS => {
+                let lost = record.event.extra[0].unwrap_or(0);
+                *self.lost_records.entry(record.cpu).or_default() += u64::from(lost);
+            }
+            _ => (),
+        }
+    }
+
+    /// Consumes the analyzer, returning the collected [`SummaryReport`].
+    pub fn finish(mut self) -> SummaryReport {
+        let last_tsc = self.last_tsc;
+
+        // Account the time spent in the current state up to the end of the trace
+        for (domain, (state, since)) in self.runstates {
+            self.vcpus
+                .entry(domain)
+                .or_default()
+                .runstates
+                .add(state, last_tsc.saturating_sub(since));
+        }
+
+        let mut vcpus = self
+            .vcpus
+            .into_iter()
+            .map(|(domain, data)| {
+                let mut summary = VcpuSummary::new(domain);
+                summary.runstates = data.runstates;
+                summary.exits = data.exits.into_values().collect();
+                summary.exits.sort_unstable_by_key(|e| e.reason);
+                (domain, summary)
+            })
+            .collect::<HashMap<_, _, FxBuildHasher>>();
+
+        for entry in self.hypercalls.finish().entries {
+            vcpus
+                .entry(entry.domain)
+                .or_insert_with(|| VcpuSummary::new(entry.domain))
+                .hypercalls
+                .push(entry);
+        }
+
+        let mut domains = HashMap::<_, _, FxBuildHasher>::default();
+        for (domain, summary) in vcpus {
+            domains
+                .entry(domain.kind)
+                .or_insert_with(Vec::new)
+                .push(summary);
+        }
+
+        let mut domains = domains
+            .into_iter()
+            .map(|(kind, mut vcpus)| {
+                vcpus.sort_unstable_by_key(|v| v.domain.vcpu);
+
+                let mut runstates = RunstateTimes::default();
+                vcpus.iter().for_each(|v| runstates.merge(&v.runstates));
+
+                DomainSummary {
+                    kind,
+                    runstates,
+                    vcpus,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        domains.sort_unstable_by_key(|d| u16::from(d.kind));
+
+        let mut lost_records = self.lost_records.into_iter().collect::<Vec<_>>();
+        lost_records.sort_unstable();
+
+        SummaryReport {
+            first_tsc: self.first_tsc.unwrap_or(0),
+            last_tsc,
+            lost_records,
+            domains,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SummaryAnalyzer, SummaryReport};
+    use crate::{
+        record::{Domain, DomainKind},
+        RecordReader, TraceBuilder,
+    };
+
+    #[test]
+    fn summary_test() {
+        let trace = TraceBuilder::new()
+            .schedule(0, Domain::new(DomainKind::Guest(5), 0))
+            .event(10, 0x00081102, &[30]) // IO_INSTRUCTION
+            .event(25, 0x00081001, &[])
+            .event(40, 0x00021021, &[0x00050000]) // running -> blocked
+            .event(50, 0x0001F001, &[3]) // lost records
+            .build()
+            .unwrap();
+
+        let report = SummaryReport::from_trace(&trace);
+        let domain = &report.domains()[0];
+        let vcpu = &domain.vcpus()[0];
+
+        assert_eq!(report.lost_records(), &[(0, 3)]);
+        assert_eq!(domain.kind(), DomainKind::Guest(5));
+        assert_eq!(vcpu.runstates().running(), 40);
+        assert_eq!(vcpu.runstates().blocked(), 10);
+        assert_eq!(vcpu.exits()[0].name(), Some("IO_INSTRUCTION"));
+        assert_eq!(vcpu.exits()[0].avg_cycles(), Some(15));
+    }
+
+    #[test]
+    fn backwards_exit_test() {
+        let bytes = TraceBuilder::new()
+            .schedule(0, Domain::new(DomainKind::Guest(5), 0))
+            .event(100, 0x00081102, &[30])
+            .event(90, 0x00081001, &[])
+            .to_bytes()
+            .unwrap();
+
+        // Pushed in file order, not sorted by TSC
+        let mut analyzer = SummaryAnalyzer::new();
+        for record in RecordReader::new(bytes.as_slice()) {
+            analyzer.push(&record.unwrap());
+        }
+
+        let report = analyzer.finish();
+        let vcpu = &report.domains()[0].vcpus()[0];
+
+        assert_eq!(vcpu.exits()[0].avg_cycles(), Some(0));
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use fxhash::FxBuildHasher;
+
+use crate::{
+    record::{Domain, Event, EventCode, Record, EVENT_EXTRA_CAPACITY},
+    trc::{TRC_SCHED_RUNSTATE_CHANGE, TRC_TRACE_CPU_CHANGE},
+    Error, Result, Trace, TraceWriter,
+};
+
+/// Builder of small, deterministic XenTrace traces.
+///
+/// Records are written in the order they are added, in segments of the
+/// CPU selected with [`cpu`](TraceBuilder::cpu) (CPU 0 by default).
+/// The produced bytes are a valid XenTrace binary file, so the built
+/// [`Trace`] goes through the same parsing (and domain tracking) as
+/// the real ones.
+///
+/// # Examples
+///
+/// ```
+/// use xentrace_parser::{
+///     record::{Domain, DomainKind},
+///     Result, TraceBuilder,
+/// };
+///
+/// fn main() -> Result<()> {
+///     let guest = Domain::new(DomainKind::Guest(1), 0);
+///
+///     let trace = TraceBuilder::new()
+///         .cpu(2)
+///         .schedule(100, guest)
+///         .event(110, 0x00081102, &[30, 0xFFFF0000, 0])
+///         .event(150, 0x00081001, &[])
+///         .build()?;
+///
+///     assert_eq!(trace.record_count(), 3);
+///     assert_eq!(trace[1].cpu(), 2);
+///     assert_eq!(trace[1].domain(), &guest);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct TraceBuilder {
+    cpu: u32,
+    running: HashMap<u32, Domain, FxBuildHasher>,
+    records: Vec<Record>,
+    error: Option<Error>,
+}
+
+impl TraceBuilder {
+    /// Constructs a new, empty `TraceBuilder`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects the CPU of the following records, starting a new segment.
+    pub fn cpu(mut self, cpu: u32) -> Self {
+        self.cpu = cpu;
+        self
+    }
+
+    /// Adds a schedule switch to the given [`Domain`] on the current CPU.
+    ///
+    /// The switch is made of the runstate changes of the virtual processors
+    /// involved: *running* to *runnable* for the previous one (if any), and
+    /// *runnable* to *running* for the new one.
+    pub fn schedule(mut self, tsc: u64, domain: Domain) -> Self {
+        if let Some(prev) = self.running.insert(self.cpu, domain) {
+            // TRC_SCHED_RUNSTATE_CHANGE (old: 0, new: 1)
+            self = self.event(tsc, TRC_SCHED_RUNSTATE_CHANGE | 0x010, &[prev.into()]);
+        }
+
+        // TRC_SCHED_RUNSTATE_CHANGE (old: 1, new: 0)
+        self.event(tsc, TRC_SCHED_RUNSTATE_CHANGE | 0x100, &[domain.into()])
+    }
+
+    /// Adds an arbitrary event on the current CPU.
+    ///
+    /// **Note:** Building fails if the event has more than
+    /// [`EVENT_EXTRA_CAPACITY`] extra values or if its code is
+    /// the reserved `TRC_TRACE_CPU_CHANGE` one.
+    pub fn event<C: Into<EventCode>>(mut self, tsc: u64, code: C, extra: &[u32]) -> Self {
+        let code = code.into();
+
+        if self.error.is_none() && code == TRC_TRACE_CPU_CHANGE {
+            self.error = Some(Error::new(
+                "Failed to add event: CPU change records are reserved",
+            ));
+        }
+
+        if self.error.is_none() && extra.len() > EVENT_EXTRA_CAPACITY {
+            self.error = Some(Error::new(format_args!(
+                "Failed to add event: {} extra values > {}",
+                extra.len(),
+                EVENT_EXTRA_CAPACITY
+            )));
+        }
+
+        let mut extras = [None; EVENT_EXTRA_CAPACITY];
+        for (entry, value) in extras.iter_mut().zip(extra) {
+            *entry = Some(*value);
+        }
+
+        self.records.push(Record {
+            cpu: self.cpu,
+            domain: Domain::default(),
+            event: Event {
+                code,
+                tsc,
+                extra: extras,
+            },
+        });
+
+        self
+    }
+
+    /// Returns the XenTrace binary data of the trace.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an invalid event has been added.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        if let Some(error) = &self.error {
+            return Err(Error::new(error));
+        }
+
+        let mut writer = TraceWriter::new(Vec::new());
+        for record in &self.records {
+            writer.write_record(record)?;
+        }
+
+        writer.finish()
+    }
+
+    /// Builds the [`Trace`], parsing the XenTrace binary data of the trace.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an invalid event has been added.
+    pub fn build(&self) -> Result<Trace> {
+        self.to_bytes().and_then(Trace::from_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TraceBuilder;
+    use crate::record::{Domain, DomainKind};
+
+    #[test]
+    fn schedule_test() {
+        let dom1 = Domain::new(DomainKind::Guest(1), 0);
+        let dom2 = Domain::new(DomainKind::Guest(2), 1);
+
+        let trace = TraceBuilder::new()
+            .schedule(10, dom1)
+            .event(20, 0x00081001, &[])
+            .cpu(1)
+            .event(15, 0x00081001, &[])
+            .cpu(0)
+            .schedule(30, dom2)
+            .event(40, 0x00081001, &[])
+            .build()
+            .unwrap();
+
+        let domains = trace.iter().map(|r| *r.domain()).collect::<Vec<_>>();
+
+        assert_eq!(trace.cpu_count(), 1);
+        assert_eq!(domains[1], Domain::default()); // CPU 1, no scheduling
+        assert_eq!(domains[2], dom1);
+        assert_eq!(domains[3], dom1); // "running -> runnable" of dom1
+        assert_eq!(domains[5], dom2);
+    }
+
+    #[test]
+    fn invalid_event_test() {
+        let builder = TraceBuilder::new().event(0, 0x00081001, &[0; 8]);
+
+        assert!(builder.to_bytes().is_err());
+        assert!(builder.build().is_err());
+    }
+}
@@ -40,13 +40,11 @@ impl Error {
     /// # Examples
     ///
     /// ```
-    /// use std::io;
-    /// use xentrace_parser::{Error, error::ErrorSource};
+    /// use xentrace_parser::{error::ErrorSource, Trace};
     ///
-    /// let io_error = io::Error::new(io::ErrorKind::NotFound, "File not found");
-    /// let custom_error = Error::io_error("Custom I/O error", io_error);
+    /// let error = Trace::from_file("/path/to/missing.bin").unwrap_err();
     ///
-    /// assert_eq!(custom_error.error_source(), &ErrorSource::Io(io_error));
+    /// assert!(matches!(error.error_source(), ErrorSource::Io(_)));
     /// ```
     pub fn error_source(&self) -> &ErrorSource {
         &self.source
@@ -57,13 +55,11 @@ impl Error {
     /// # Examples
     ///
     /// ```
-    /// use std::io;
-    /// use xentrace_parser::{Error, error::ErrorSource};
+    /// use xentrace_parser::Trace;
     ///
-    /// let io_error = io::Error::from_raw_os_error(2);
-    /// let custom_error = Error::io_error("Custom I/O error", io_error);
+    /// let error = Trace::from_file("/path/to/missing.bin").unwrap_err();
     ///
-    /// assert_eq!(custom_error.raw_os_error(), Some(2));
+    /// assert!(error.raw_os_error().is_some());
     /// ```
     pub fn raw_os_error(&self) -> Option<i32> {
         match &self.source {
@@ -0,0 +1,232 @@
+use std::{fs, io, path::Path, sync::Arc};
+
+use arrow_array::{ArrayRef, RecordBatch, UInt16Array, UInt32Array, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+
+use crate::{
+    record::{Record, EVENT_EXTRA_CAPACITY},
+    Error, Result,
+};
+
+/// The count of records of the batches written by [`ParquetWriter`].
+const BATCH_SIZE: usize = 64 * 1024;
+
+/// Returns the Arrow schema of the batches built by [`record_batch`].
+///
+/// The columns are the `cpu` (`UInt32`), the `domid` and `vcpu` (`UInt16`),
+/// the `tsc` (`UInt64`), the event `code` (`UInt32`) and the extra values,
+/// from `extra0` to `extra6` (`UInt32`, null if missing).
+///
+/// *Available with the `arrow` feature.*
+pub fn schema() -> SchemaRef {
+    let mut fields = vec![
+        Field::new("cpu", DataType::UInt32, false),
+        Field::new("domid", DataType::UInt16, false),
+        Field::new("vcpu", DataType::UInt16, false),
+        Field::new("tsc", DataType::UInt64, false),
+        Field::new("code", DataType::UInt32, false),
+    ];
+
+    fields.extend(
+        (0..EVENT_EXTRA_CAPACITY)
+            .map(|i| Field::new(format!("extra{}", i), DataType::UInt32, true)),
+    );
+
+    Arc::new(Schema::new(fields))
+}
+
+/// Converts records (*e.g.* the ones of a [`Trace`](crate::Trace))
+/// to an Arrow `RecordBatch`, with the columns of [`schema`].
+///
+/// *Available with the `arrow` feature.*
+///
+/// # Errors
+///
+/// This function will return an error if it fails to build the batch.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{export, Result, Trace};
+///
+/// fn main() -> Result<()> {
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///     let batch = export::record_batch(&trace)?;
+///
+///     println!("{} rows", batch.num_rows());
+///     Ok(())
+/// }
+/// ```
+pub fn record_batch(records: &[Record]) -> Result<RecordBatch> {
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt32Array::from_iter_values(records.iter().map(|r| r.cpu))),
+        Arc::new(UInt16Array::from_iter_values(
+            records.iter().map(|r| u16::from(r.domain.kind)),
+        )),
+        Arc::new(UInt16Array::from_iter_values(
+            records.iter().map(|r| r.domain.vcpu),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            records.iter().map(|r| r.event.tsc),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            records.iter().map(|r| r.event.code.value()),
+        )),
+    ];
+
+    columns.extend((0..EVENT_EXTRA_CAPACITY).map(|i| {
+        Arc::new(UInt32Array::from_iter(
+            records.iter().map(|r| r.event.extra[i]),
+        )) as ArrayRef
+    }));
+
+    RecordBatch::try_new(schema(), columns)
+        .map_err(|e| Error::new(format_args!("Failed to build record batch: {}", e)))
+}
+
+/// Writer of records in the Apache Parquet format,
+/// with the columns of [`schema`].
+///
+/// Records are buffered and written by row groups of 65536 records.
+///
+/// *Available with the `arrow` feature.*
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{export::ParquetWriter, RecordReader, Result};
+///
+/// fn main() -> Result<()> {
+///     let mut writer = ParquetWriter::create("/path/to/xentrace.parquet")?;
+///
+///     for record in RecordReader::from_file("/path/to/xentrace.bin")? {
+///         writer.write_record(&record?)?;
+///     }
+///
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+pub struct ParquetWriter<W: io::Write + Send> {
+    wtr: ArrowWriter<W>,
+    records: Vec<Record>,
+}
+
+impl ParquetWriter<fs::File> {
+    /// Constructs a `ParquetWriter` to a file specified by its path,
+    /// which is created (or truncated).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to create the file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        fs::File::create(path)
+            .map_err(|e| Error::io_error("Failed to create Parquet file", e))
+            .and_then(Self::new)
+    }
+}
+
+impl<W: io::Write + Send> ParquetWriter<W> {
+    /// Constructs a `ParquetWriter` to any type that implements `io::Write`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to set up the writer.
+    pub fn new(writer: W) -> Result<Self> {
+        let wtr = ArrowWriter::try_new(writer, schema(), None)
+            .map_err(|e| Error::new(format_args!("Failed to create Parquet writer: {}", e)))?;
+
+        Ok(Self {
+            wtr,
+            records: Vec::with_capacity(BATCH_SIZE),
+        })
+    }
+
+    /// Writes a single record, as a row.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write a row group.
+    pub fn write_record(&mut self, record: &Record) -> Result<()> {
+        self.records.push(record.clone());
+
+        if self.records.len() >= BATCH_SIZE {
+            self.write_batch()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the buffered records and the file metadata,
+    /// then returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write
+    /// the last row group or the file metadata.
+    pub fn finish(mut self) -> Result<W> {
+        self.write_batch()?;
+        self.wtr
+            .into_inner()
+            .map_err(|e| Error::new(format_args!("Failed to finish Parquet file: {}", e)))
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        let batch = record_batch(&self.records)?;
+        self.records.clear();
+
+        self.wtr
+            .write(&batch)
+            .map_err(|e| Error::new(format_args!("Failed to write Parquet row group: {}", e)))
+    }
+}
+
+impl<W: io::Write + Send> std::fmt::Debug for ParquetWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParquetWriter")
+            .field("records", &self.records.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, types::UInt32Type};
+
+    use super::{record_batch, ParquetWriter};
+    use crate::{
+        record::{Domain, DomainKind},
+        TraceBuilder,
+    };
+
+    #[test]
+    fn record_batch_test() {
+        let trace = TraceBuilder::new()
+            .cpu(1)
+            .schedule(10, Domain::new(DomainKind::Guest(5), 1))
+            .event(20, 0x00081102, &[48, 0x1000, 0])
+            .build()
+            .unwrap();
+
+        let batch = record_batch(&trace).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 12);
+
+        let code = batch.column_by_name("code").unwrap();
+        assert_eq!(code.as_primitive::<UInt32Type>().value(1), 0x00081102);
+
+        let extra1 = batch.column_by_name("extra1").unwrap();
+        assert_eq!(extra1.as_primitive::<UInt32Type>().value(1), 0x1000);
+        assert!(extra1.is_null(0));
+
+        let mut writer = ParquetWriter::new(Vec::new()).unwrap();
+        trace
+            .iter()
+            .try_for_each(|r| writer.write_record(r))
+            .unwrap();
+        let output = writer.finish().unwrap();
+
+        assert!(output.starts_with(b"PAR1") && output.ends_with(b"PAR1"));
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+};
+
+use fxhash::FxBuildHasher;
+
+use super::{
+    decode::{decode, find_str, Value},
+    jsonl::tsc_to_ns,
+};
+use crate::{
+    analysis::Occupancy,
+    record::{Event, Record},
+    trc::{TRC_HVM_VMENTRY, TRC_HVM_VMEXIT, TRC_HVM_VMEXIT64},
+    Error, Result, Trace,
+};
+
+/// Writer of a [`Trace`] in the Chrome Trace Event Format (JSON), as opened
+/// by [Perfetto](https://ui.perfetto.dev) and `chrome://tracing`.
+///
+/// Each physical CPU is a track (thread) of a single `Xen` process, on which:
+/// - the [occupancy](Occupancy) intervals are duration slices named after the
+///   virtual CPU (*e.g.* `dom5 vcpu1`), the idle ones being left out by default;
+/// - the time from a VMEXIT to the next VMENTRY is a duration slice (nested in
+///   the one of the virtual CPU) named after the exit reason (*e.g.*
+///   `VMEXIT EPT_VIOLATION`);
+/// - the other events are instants, named after the event (or its code).
+///
+/// Timestamps are converted to microseconds using the frequency set with
+/// [`cpu_hz`](ChromeTraceWriter::cpu_hz), otherwise TSC values are used as
+/// nanoseconds.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{export::ChromeTraceWriter, Result, Trace};
+///
+/// fn main() -> Result<()> {
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///
+///     ChromeTraceWriter::create("/path/to/xentrace.json")?
+///         .cpu_hz(2_400_000_000)
+///         .write_trace(&trace)?;
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ChromeTraceWriter<W: io::Write> {
+    wtr: W,
+    cpu_hz: Option<u64>,
+    idle: bool,
+    line: String,
+    count: usize,
+}
+
+impl ChromeTraceWriter<io::BufWriter<fs::File>> {
+    /// Constructs a `ChromeTraceWriter` to a file specified by its path,
+    /// which is created (or truncated).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to create the file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        fs::File::create(path)
+            .map_err(|e| Error::io_error("Failed to create Chrome trace file", e))
+            .map(io::BufWriter::new)
+            .map(Self::new)
+    }
+}
+
+impl<W: io::Write> ChromeTraceWriter<W> {
+    /// Constructs a `ChromeTraceWriter` to any type that implements `io::Write`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            wtr: writer,
+            cpu_hz: None,
+            idle: false,
+            line: String::with_capacity(256),
+            count: 0,
+        }
+    }
+
+    /// Sets the frequency (in Hz) of the TSC of the traced host, used
+    /// to convert the TSC values to microseconds.
+    pub fn cpu_hz(mut self, hz: u64) -> Self {
+        self.cpu_hz = (hz > 0).then_some(hz);
+        self
+    }
+
+    /// Sets whether the intervals during which a CPU was running
+    /// the idle domain are written as slices (`false` by default).
+    pub fn idle(mut self, idle: bool) -> Self {
+        self.idle = idle;
+        self
+    }
+
+    /// Writes the whole trace as a single JSON document, then flushes
+    /// and returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write the
+    /// document or to flush the writer.
+    pub fn write_trace(mut self, trace: &Trace) -> Result<W> {
+        self.write_str(r#"{"displayTimeUnit":"ns","traceEvents":["#)?;
+
+        let occupancy = Occupancy::from_trace(trace);
+
+        self.write_event(|_, line| {
+            write!(
+                line,
+                r#"{{"name":"process_name","ph":"M","pid":0,"args":{{"name":"Xen"}}}}"#
+            )
+        })?;
+
+        for cpu in occupancy.cpus() {
+            let cpu = cpu.cpu();
+            self.write_event(|_, line| {
+                write!(
+                    line,
+                    r#"{{"name":"thread_name","ph":"M","pid":0,"tid":{},"args":{{"name":"pCPU {}"}}}}"#,
+                    cpu, cpu
+                )?;
+                write!(
+                    line,
+                    r#",{{"name":"thread_sort_index","ph":"M","pid":0,"tid":{},"args":{{"sort_index":{}}}}}"#,
+                    cpu, cpu
+                )
+            })?;
+        }
+
+        for cpu in occupancy.cpus() {
+            for interval in cpu.intervals() {
+                if interval.is_idle() && !self.idle {
+                    continue;
+                }
+
+                let domain = interval.domain();
+                self.write_event(|this, line| {
+                    write!(
+                        line,
+                        r#"{{"name":"{} vcpu{}","cat":"vcpu","ph":"X","pid":0,"tid":{},"ts":"#,
+                        domain.kind,
+                        domain.vcpu,
+                        cpu.cpu()
+                    )?;
+                    this.write_ts(line, interval.start_tsc())?;
+                    line.push_str(r#","dur":"#);
+                    this.write_ts(line, interval.duration())?;
+                    write!(
+                        line,
+                        r#","args":{{"dom":{},"vcpu":{}}}}}"#,
+                        u16::from(domain.kind),
+                        domain.vcpu
+                    )
+                })?;
+            }
+        }
+
+        // The pending VMEXIT of each CPU, with the timestamp it lasts until
+        let mut exits = HashMap::<u32, (&Record, u64), FxBuildHasher>::default();
+
+        for record in trace.iter() {
+            let code = record.event.code.value();
+
+            if let Entry::Occupied(mut entry) = exits.entry(record.cpu) {
+                let (exit, end_tsc) = entry.get_mut();
+                if code == TRC_HVM_VMENTRY || exit.domain != record.domain {
+                    let (exit, _) = entry.remove();
+                    self.write_exit(exit, record.event.tsc)?;
+                } else {
+                    *end_tsc = record.event.tsc;
+                }
+            }
+
+            match code {
+                TRC_HVM_VMEXIT | TRC_HVM_VMEXIT64 => {
+                    // A VMEXIT without VMENTRY ends at the next one
+                    if let Some((exit, _)) = exits.insert(record.cpu, (record, record.event.tsc)) {
+                        self.write_exit(exit, record.event.tsc)?;
+                    }
+                }
+                TRC_HVM_VMENTRY => (),
+                _ => self.write_instant(record)?,
+            }
+        }
+
+        let mut exits = exits.into_values().collect::<Vec<_>>();
+        exits.sort_unstable_by_key(|(exit, _)| exit.cpu);
+        for (exit, end_tsc) in exits {
+            self.write_exit(exit, end_tsc)?;
+        }
+
+        self.write_str("]}\n")?;
+        self.wtr
+            .flush()
+            .map_err(|e| Error::io_error("Failed to flush Chrome trace writer", e))?;
+
+        Ok(self.wtr)
+    }
+
+    fn write_exit(&mut self, exit: &Record, end_tsc: u64) -> Result<()> {
+        let fields = decode(&exit.event);
+        let reason = find_str(&fields, "exit_reason_name");
+
+        self.write_event(|this, line| {
+            line.push_str(r#"{"name":"VMEXIT"#);
+            if let Some(reason) = reason {
+                write!(line, " {}", reason)?;
+            }
+            write!(
+                line,
+                r#"","cat":"vmexit","ph":"X","pid":0,"tid":{},"ts":"#,
+                exit.cpu
+            )?;
+            this.write_ts(line, exit.event.tsc)?;
+            line.push_str(r#","dur":"#);
+            this.write_ts(line, end_tsc - exit.event.tsc)?;
+            line.push_str(r#","args":"#);
+            write_args(line, &exit.event, &fields)?;
+            line.push('}');
+            Ok(())
+        })
+    }
+
+    fn write_instant(&mut self, record: &Record) -> Result<()> {
+        let event = &record.event;
+        let fields = decode(event);
+
+        self.write_event(|this, line| {
+            match event.code.name() {
+                Some(name) => write!(line, r#"{{"name":"{}""#, name)?,
+                None => write!(line, r#"{{"name":"{:#010X}""#, event.code.value())?,
+            }
+            write!(
+                line,
+                r#","cat":"event","ph":"i","s":"t","pid":0,"tid":{},"ts":"#,
+                record.cpu
+            )?;
+            this.write_ts(line, event.tsc)?;
+            line.push_str(r#","args":"#);
+            write_args(line, event, &fields)?;
+            line.push('}');
+            Ok(())
+        })
+    }
+
+    /// Writes a timestamp (or a duration) in microseconds.
+    fn write_ts(&self, line: &mut String, tsc: u64) -> std::fmt::Result {
+        let ns = match self.cpu_hz {
+            Some(hz) => tsc_to_ns(tsc, hz),
+            None => tsc,
+        };
+
+        write!(line, "{}.{:03}", ns / 1000, ns % 1000)
+    }
+
+    /// Writes a single event, formatted by `format`, to the `traceEvents` list.
+    fn write_event<F>(&mut self, format: F) -> Result<()>
+    where
+        F: FnOnce(&Self, &mut String) -> std::fmt::Result,
+    {
+        let mut line = std::mem::take(&mut self.line);
+        line.clear();
+        if self.count > 0 {
+            line.push(',');
+        }
+        line.push('\n');
+
+        // Writing to a "String" cannot fail
+        let _ = format(self, &mut line);
+        self.count += 1;
+
+        let result = self.write_str(&line);
+        self.line = line;
+        result
+    }
+
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        self.wtr
+            .write_all(s.as_bytes())
+            .map_err(|e| Error::io_error("Failed to write Chrome trace event", e))
+    }
+}
+
+// Strings are names of this crate, JSON-safe with no escaping
+fn write_args(line: &mut String, event: &Event, fields: &[(&str, Value)]) -> std::fmt::Result {
+    line.push_str(r#"{"extras":["#);
+    for (i, value) in event.extra.iter().map_while(|e| *e).enumerate() {
+        let sep = if i > 0 { "," } else { "" };
+        write!(line, "{}{}", sep, value)?;
+    }
+    line.push(']');
+
+    for (name, value) in fields {
+        match value {
+            Value::Num(value) => write!(line, r#","{}":{}"#, name, value)?,
+            Value::Str(value) => write!(line, r#","{}":"{}""#, name, value)?,
+        }
+    }
+
+    line.push('}');
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChromeTraceWriter;
+    use crate::{
+        record::{Domain, DomainKind},
+        TraceBuilder,
+    };
+
+    #[test]
+    fn chrome_test() {
+        let trace = TraceBuilder::new()
+            .cpu(1)
+            .schedule(1000, Domain::new(DomainKind::Guest(5), 1))
+            .event(2000, 0x00081102, &[48, 0x1000, 0])
+            .event(2500, 0x00082012, &[29])
+            .event(3000, 0x00081001, &[])
+            .schedule(5000, Domain::new(DomainKind::Idle, 0))
+            .build()
+            .unwrap();
+
+        let output = ChromeTraceWriter::new(Vec::new())
+            .cpu_hz(1_000_000_000)
+            .write_trace(&trace)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let json = serde_json::from_str::<serde_json::Value>(&output).unwrap();
+        let events = json["traceEvents"].as_array().unwrap();
+
+        let names = events
+            .iter()
+            .map(|e| (e["ph"].as_str().unwrap(), e["name"].as_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("M", "process_name"),
+                ("M", "thread_name"),
+                ("M", "thread_sort_index"),
+                ("X", "dom5 vcpu1"),
+                ("i", "runnable_to_running"),
+                ("i", "VMMCALL"),
+                ("X", "VMEXIT EPT_VIOLATION"),
+                ("i", "running_to_runnable"),
+                ("i", "runnable_to_running"),
+            ]
+        );
+
+        assert_eq!(events[3]["ts"], 1.0);
+        assert_eq!(events[3]["dur"], 4.0);
+        assert_eq!(events[6]["ts"], 2.0);
+        assert_eq!(events[6]["dur"], 1.0);
+        assert_eq!(events[6]["args"]["rip"], 0x1000);
+        assert_eq!(events[5]["args"]["hypercall_name"], "sched_op");
+    }
+}
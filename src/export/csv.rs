@@ -0,0 +1,264 @@
+use std::{fmt::Write as _, fs, io, path::Path};
+
+use crate::{record::Record, Error, Result};
+
+/// A column of the CSV files written by [`CsvWriter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Column {
+    /// The processor id (of the host), `cpu`.
+    Cpu,
+    /// The domain id (*e.g.* `32767` for the idle domain), `domid`.
+    Domain,
+    /// The virtual processor number, `vcpu`.
+    Vcpu,
+    /// The timestamp of the event, `tsc`.
+    Tsc,
+    /// The event code, in hexadecimal (*e.g.* `0x00081002`), `code`.
+    Code,
+    /// The event class, `main`.
+    Main,
+    /// The event subclass, `sub`.
+    Sub,
+    /// The event minor, `minor`.
+    Minor,
+    /// The extra value with the given index (`0..=6`), `extraN`.
+    Extra(usize),
+    /// The name of the event (*e.g.* `VMEXIT`), `name`.
+    Name,
+}
+
+impl Column {
+    /// All the columns, in their default order.
+    pub const ALL: [Column; 16] = [
+        Column::Cpu,
+        Column::Domain,
+        Column::Vcpu,
+        Column::Tsc,
+        Column::Code,
+        Column::Main,
+        Column::Sub,
+        Column::Minor,
+        Column::Extra(0),
+        Column::Extra(1),
+        Column::Extra(2),
+        Column::Extra(3),
+        Column::Extra(4),
+        Column::Extra(5),
+        Column::Extra(6),
+        Column::Name,
+    ];
+
+    fn write_header(&self, line: &mut String) {
+        // Writing to a "String" cannot fail
+        let _ = match self {
+            Self::Cpu => write!(line, "cpu"),
+            Self::Domain => write!(line, "domid"),
+            Self::Vcpu => write!(line, "vcpu"),
+            Self::Tsc => write!(line, "tsc"),
+            Self::Code => write!(line, "code"),
+            Self::Main => write!(line, "main"),
+            Self::Sub => write!(line, "sub"),
+            Self::Minor => write!(line, "minor"),
+            Self::Extra(index) => write!(line, "extra{}", index),
+            Self::Name => write!(line, "name"),
+        };
+    }
+
+    fn write_value(&self, line: &mut String, record: &Record) {
+        let code = &record.event.code;
+
+        // Writing to a "String" cannot fail
+        let _ = match self {
+            Self::Cpu => write!(line, "{}", record.cpu),
+            Self::Domain => write!(line, "{}", u16::from(record.domain.kind)),
+            Self::Vcpu => write!(line, "{}", record.domain.vcpu),
+            Self::Tsc => write!(line, "{}", record.event.tsc),
+            Self::Code => write!(line, "{:#010X}", code.value()),
+            Self::Main => write!(line, "{}", code.main()),
+            Self::Sub => write!(line, "{}", code.sub()),
+            Self::Minor => write!(line, "{}", code.minor()),
+            Self::Extra(index) => match record.event.extra.get(*index).copied().flatten() {
+                Some(value) => write!(line, "{}", value),
+                None => Ok(()),
+            },
+            Self::Name => write!(line, "{}", code.name().unwrap_or_default()),
+        };
+    }
+}
+
+/// Writer of records in the CSV format, one row per record,
+/// preceded by a header row.
+///
+/// Missing values (*e.g.* the extra values an event does not have,
+/// or the name of an unknown event) are written as empty cells.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{
+///     export::{Column, CsvWriter},
+///     RecordReader, Result,
+/// };
+///
+/// fn main() -> Result<()> {
+///     let mut writer = CsvWriter::create("/path/to/xentrace.csv")?.columns(&[
+///         Column::Cpu,
+///         Column::Tsc,
+///         Column::Name,
+///         Column::Extra(0),
+///     ]);
+///
+///     for record in RecordReader::from_file("/path/to/xentrace.bin")? {
+///         writer.write_record(&record?)?;
+///     }
+///
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CsvWriter<W: io::Write> {
+    wtr: W,
+    columns: Vec<Column>,
+    header: bool,
+    line: String,
+}
+
+impl CsvWriter<io::BufWriter<fs::File>> {
+    /// Constructs a `CsvWriter` to a file specified by its path,
+    /// which is created (or truncated).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to create the file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        fs::File::create(path)
+            .map_err(|e| Error::io_error("Failed to create CSV file", e))
+            .map(io::BufWriter::new)
+            .map(Self::new)
+    }
+}
+
+impl<W: io::Write> CsvWriter<W> {
+    /// Constructs a `CsvWriter` to any type that implements `io::Write`,
+    /// writing [all the columns](Column::ALL).
+    pub fn new(writer: W) -> Self {
+        Self {
+            wtr: writer,
+            columns: Column::ALL.to_vec(),
+            header: false,
+            line: String::with_capacity(256),
+        }
+    }
+
+    /// Sets the columns to write, in order.
+    ///
+    /// **Note:** The columns must be set before writing the first record.
+    pub fn columns(mut self, columns: &[Column]) -> Self {
+        self.columns = columns.to_vec();
+        self
+    }
+
+    /// Writes a single record, as a row.
+    ///
+    /// The header row is written before the first record.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write the row.
+    pub fn write_record(&mut self, record: &Record) -> Result<()> {
+        self.write_header()?;
+
+        self.line.clear();
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                self.line.push(',');
+            }
+            column.write_value(&mut self.line, record);
+        }
+        self.line.push('\n');
+
+        self.wtr
+            .write_all(self.line.as_bytes())
+            .map_err(|e| Error::io_error("Failed to write CSV row", e))
+    }
+
+    /// Flushes the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to flush the writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.wtr
+            .flush()
+            .map_err(|e| Error::io_error("Failed to flush CSV writer", e))
+    }
+
+    /// Writes the header row, if no record has been written,
+    /// then flushes and returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write the header
+    /// row or to flush the writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.write_header()?;
+        self.flush()?;
+        Ok(self.wtr)
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        if self.header {
+            return Ok(());
+        }
+
+        self.line.clear();
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                self.line.push(',');
+            }
+            column.write_header(&mut self.line);
+        }
+        self.line.push('\n');
+
+        self.header = true;
+        self.wtr
+            .write_all(self.line.as_bytes())
+            .map_err(|e| Error::io_error("Failed to write CSV header", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Column, CsvWriter};
+    use crate::TraceBuilder;
+
+    #[test]
+    fn csv_test() {
+        let trace = TraceBuilder::new()
+            .cpu(1)
+            .event(10, 0x00081102, &[48, 0x1000, 0])
+            .event(20, 0x00081FFF, &[])
+            .build()
+            .unwrap();
+
+        let mut writer = CsvWriter::new(Vec::new());
+        trace
+            .iter()
+            .try_for_each(|r| writer.write_record(r))
+            .unwrap();
+        let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(
+            output,
+            "cpu,domid,vcpu,tsc,code,main,sub,minor,extra0,extra1,extra2,extra3,extra4,extra5,extra6,name\n\
+             1,32768,0,10,0x00081102,8,1,258,48,4096,0,,,,,VMEXIT64\n\
+             1,32768,0,20,0x00081FFF,8,1,4095,,,,,,,,\n"
+        );
+
+        let writer = CsvWriter::new(Vec::new()).columns(&[Column::Tsc, Column::Extra(1)]);
+        let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(output, "tsc,extra1\n");
+    }
+}
@@ -0,0 +1,362 @@
+use std::{collections::HashMap, fmt::Write as _, io};
+
+use fxhash::FxBuildHasher;
+
+use crate::{record::Record, trc::event_name, Error, Result};
+
+/// The magic number starting each packet.
+const CTF_MAGIC: u32 = 0xC1FC1FC1;
+
+/// The size of the packet header and context, in bytes.
+const PACKET_HEADER_LEN: usize = 44;
+
+/// The size of the events of a packet, beyond which it is written.
+const PACKET_CAPACITY: usize = 64 * 1024;
+
+/// The name of the metadata file of the trace.
+const METADATA_NAME: &str = "metadata";
+
+/// The stream of a single CPU, with the events of its current packet.
+#[derive(Debug)]
+struct CpuStream<W> {
+    name: String,
+    wtr: W,
+    cpu: u32,
+    events: Vec<u8>,
+    begin_tsc: u64,
+    end_tsc: u64,
+}
+
+impl<W: io::Write> CpuStream<W> {
+    fn write_packet(&mut self) -> Result<()> {
+        if self.events.is_empty() {
+            return Ok(());
+        }
+
+        let size = (PACKET_HEADER_LEN + self.events.len()) as u64 * 8;
+
+        let mut header = Vec::with_capacity(PACKET_HEADER_LEN);
+        header.extend_from_slice(&CTF_MAGIC.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes()); // stream_id
+        header.extend_from_slice(&self.begin_tsc.to_le_bytes());
+        header.extend_from_slice(&self.end_tsc.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes()); // content_size
+        header.extend_from_slice(&size.to_le_bytes()); // packet_size
+        header.extend_from_slice(&self.cpu.to_le_bytes());
+
+        self.wtr
+            .write_all(&header)
+            .and_then(|_| self.wtr.write_all(&self.events))
+            .map_err(|e| Error::io_error("Failed to write CTF packet", e))?;
+
+        self.events.clear();
+        Ok(())
+    }
+}
+
+/// Writer of records as a [Common Trace Format](https://diamon.org/ctf/v1.8.3/)
+/// 1.8 trace, as opened by Trace Compass or `babeltrace`.
+///
+/// A CTF trace is a directory made of a `metadata` file (describing the
+/// trace in TSDL) and of a stream file for each CPU (*e.g.* `stream_0`),
+/// all of them being created by the `create` function, given their name.
+///
+/// Each distinct event code (and count of extra values) is declared as an
+/// event named after it (*e.g.* `VMEXIT64`, or `0x0008FFFF` if unknown),
+/// whose fields are the extra values (`extra0`, `extra1`, ...). The domain
+/// and virtual CPU of each event are in its context (`dom` and `vcpu`),
+/// the CPU in the context of the packets (`cpu_id`).
+///
+/// **Note:** Records must be written in TSC order for each CPU, as they
+/// are found in a trace file or in a [`Trace`](crate::Trace).
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::{
+///     fs::{self, File},
+///     io::BufWriter,
+/// };
+/// use xentrace_parser::{export::CtfWriter, RecordReader, Result};
+///
+/// fn main() -> Result<()> {
+///     fs::create_dir_all("/path/to/xentrace-ctf").unwrap();
+///
+///     let mut writer = CtfWriter::new(|name| {
+///         File::create(format!("/path/to/xentrace-ctf/{}", name)).map(BufWriter::new)
+///     })
+///     .cpu_hz(2_400_000_000);
+///
+///     for record in RecordReader::from_file("/path/to/xentrace.bin")? {
+///         writer.write_record(&record?)?;
+///     }
+///
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CtfWriter<W, F> {
+    create: F,
+    cpu_hz: Option<u64>,
+    streams: Vec<CpuStream<W>>,
+    indices: HashMap<u32, usize, FxBuildHasher>,
+    events: HashMap<(u32, usize), u32, FxBuildHasher>,
+}
+
+impl<W, F> CtfWriter<W, F>
+where
+    W: io::Write,
+    F: FnMut(&str) -> io::Result<W>,
+{
+    /// Constructs a `CtfWriter` writing the files of the trace
+    /// to the writers returned by `create`, given their name.
+    pub fn new(create: F) -> Self {
+        Self {
+            create,
+            cpu_hz: None,
+            streams: Vec::new(),
+            indices: HashMap::default(),
+            events: HashMap::default(),
+        }
+    }
+
+    /// Sets the frequency (in Hz) of the TSC of the traced host,
+    /// used as the frequency of the clock of the trace.
+    ///
+    /// Without it, TSC values are considered as nanoseconds.
+    pub fn cpu_hz(mut self, hz: u64) -> Self {
+        self.cpu_hz = (hz > 0).then_some(hz);
+        self
+    }
+
+    /// Writes a single record, as an event of the stream of its CPU.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to create the stream
+    /// file or to write a packet.
+    pub fn write_record(&mut self, record: &Record) -> Result<()> {
+        let event = &record.event;
+        let extra_count = event.extra.iter().take_while(|e| e.is_some()).count();
+
+        let next_id = self.events.len() as u32;
+        let id = *self
+            .events
+            .entry((event.code.value(), extra_count))
+            .or_insert(next_id);
+
+        let index = match self.indices.get(&record.cpu) {
+            Some(index) => *index,
+            None => {
+                let name = format!("stream_{}", record.cpu);
+                let wtr = (self.create)(&name)
+                    .map_err(|e| Error::io_error("Failed to create CTF stream file", e))?;
+
+                self.streams.push(CpuStream {
+                    name,
+                    wtr,
+                    cpu: record.cpu,
+                    events: Vec::with_capacity(PACKET_CAPACITY + 64),
+                    begin_tsc: event.tsc,
+                    end_tsc: event.tsc,
+                });
+                self.indices.insert(record.cpu, self.streams.len() - 1);
+                self.streams.len() - 1
+            }
+        };
+
+        let stream = &mut self.streams[index];
+        if stream.events.is_empty() {
+            stream.begin_tsc = event.tsc;
+        }
+        stream.end_tsc = event.tsc;
+
+        let data = &mut stream.events;
+        data.extend_from_slice(&id.to_le_bytes());
+        data.extend_from_slice(&event.tsc.to_le_bytes());
+        data.extend_from_slice(&u16::from(record.domain.kind).to_le_bytes());
+        data.extend_from_slice(&record.domain.vcpu.to_le_bytes());
+        for value in event.extra.iter().map_while(|e| *e) {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        if data.len() >= PACKET_CAPACITY {
+            stream.write_packet()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last packets of the streams and the metadata of the trace,
+    /// then flushes and returns the writers with the names of their files.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write the last
+    /// packets, to create or write the metadata file or to flush a writer.
+    pub fn finish(mut self) -> Result<Vec<(String, W)>> {
+        let mut metadata = (self.create)(METADATA_NAME)
+            .map_err(|e| Error::io_error("Failed to create CTF metadata file", e))?;
+
+        metadata
+            .write_all(self.metadata().as_bytes())
+            .map_err(|e| Error::io_error("Failed to write CTF metadata", e))?;
+
+        let mut files = vec![(METADATA_NAME.to_string(), metadata)];
+        for mut stream in self.streams {
+            stream.write_packet()?;
+            files.push((stream.name, stream.wtr));
+        }
+
+        for (_, wtr) in files.iter_mut() {
+            wtr.flush()
+                .map_err(|e| Error::io_error("Failed to flush CTF writer", e))?;
+        }
+
+        Ok(files)
+    }
+
+    /// Returns the TSDL description of the trace.
+    fn metadata(&self) -> String {
+        let mut events = self.events.iter().collect::<Vec<_>>();
+        events.sort_unstable_by_key(|(_, id)| **id);
+
+        // Writing to a "String" cannot fail
+        let mut tsdl = String::from(METADATA_HEADER);
+        let _ = write!(
+            tsdl,
+            "clock {{\n\tname = tsc;\n\tfreq = {};\n\toffset = 0;\n}};\n\n",
+            self.cpu_hz.unwrap_or(1_000_000_000)
+        );
+        tsdl.push_str(METADATA_STREAM);
+
+        for ((code, extra_count), id) in events {
+            let _ = write!(tsdl, "\nevent {{\n\tname = \"");
+            let _ = match event_name(*code) {
+                Some(name) => write!(tsdl, "{}", name),
+                None => write!(tsdl, "{:#010X}", code),
+            };
+            let _ = write!(tsdl, "\";\n\tid = {};\n\tstream_id = 0;\n", id);
+
+            if *extra_count > 0 {
+                tsdl.push_str("\tfields := struct {\n");
+                for i in 0..*extra_count {
+                    let _ = writeln!(tsdl, "\t\tuint32_t extra{};", i);
+                }
+                tsdl.push_str("\t};\n");
+            }
+
+            tsdl.push_str("};\n");
+        }
+
+        tsdl
+    }
+}
+
+const METADATA_HEADER: &str = "/* CTF 1.8 */
+
+typealias integer { size = 16; align = 8; signed = false; } := uint16_t;
+typealias integer { size = 32; align = 8; signed = false; } := uint32_t;
+typealias integer { size = 64; align = 8; signed = false; } := uint64_t;
+
+trace {
+\tmajor = 1;
+\tminor = 8;
+\tbyte_order = le;
+\tpacket.header := struct {
+\t\tuint32_t magic;
+\t\tuint32_t stream_id;
+\t};
+};
+
+env {
+\tdomain = \"xen\";
+\ttracer_name = \"xentrace\";
+};
+
+";
+
+const METADATA_STREAM: &str =
+    "typealias integer { size = 64; align = 8; signed = false; map = clock.tsc.value; } := tsc_t;
+
+stream {
+\tid = 0;
+\tpacket.context := struct {
+\t\ttsc_t timestamp_begin;
+\t\ttsc_t timestamp_end;
+\t\tuint64_t content_size;
+\t\tuint64_t packet_size;
+\t\tuint32_t cpu_id;
+\t};
+\tevent.header := struct {
+\t\tuint32_t id;
+\t\ttsc_t timestamp;
+\t};
+\tevent.context := struct {
+\t\tuint16_t dom;
+\t\tuint16_t vcpu;
+\t};
+};
+";
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::CtfWriter;
+    use crate::{
+        record::{Domain, DomainKind},
+        TraceBuilder,
+    };
+
+    #[test]
+    fn ctf_test() {
+        let trace = TraceBuilder::new()
+            .cpu(1)
+            .schedule(1000, Domain::new(DomainKind::Guest(5), 1))
+            .event(2000, 0x00081102, &[48, 0x1000, 0])
+            .event(3000, 0x0008FFFF, &[])
+            .build()
+            .unwrap();
+
+        let mut writer = CtfWriter::new(|_| Ok(Vec::new())).cpu_hz(1_000_000);
+        trace
+            .iter()
+            .try_for_each(|r| writer.write_record(r))
+            .unwrap();
+        let files = writer.finish().unwrap();
+
+        let names = files
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["metadata", "stream_1"]);
+
+        let metadata = String::from_utf8(files[0].1.clone()).unwrap();
+        assert!(metadata.starts_with("/* CTF 1.8 */"));
+        assert!(metadata.contains("\tfreq = 1000000;\n"));
+        assert!(metadata.contains(
+            "\tname = \"VMEXIT64\";\n\tid = 1;\n\tstream_id = 0;\n\tfields := struct {\n\t\tuint32_t extra0;\n"
+        ));
+        assert!(metadata.contains("\tname = \"0x0008FFFF\";\n\tid = 2;\n\tstream_id = 0;\n};\n"));
+
+        // Packet header and context, then the 3 events
+        let stream = &files[1].1;
+        let u32_at = |i: usize| u32::from_le_bytes(stream[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(stream[i..i + 8].try_into().unwrap());
+
+        assert_eq!(stream.len(), 44 + (16 + 4) + (16 + 12) + 16);
+        assert_eq!(u32_at(0), 0xC1FC1FC1);
+        assert_eq!((u64_at(8), u64_at(16)), (1000, 3000));
+        assert_eq!(u64_at(24), stream.len() as u64 * 8);
+        assert_eq!(u32_at(40), 1);
+
+        // VMEXIT64: id, tsc, dom, vcpu and extras
+        let event = 44 + 20;
+        assert_eq!(u32_at(event), 1);
+        assert_eq!(u64_at(event + 4), 2000);
+        assert_eq!(u32_at(event + 12), 5 | 1 << 16);
+        assert_eq!(u32_at(event + 16), 48);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{
+    record::{Domain, Event},
+    trc::{
+        hypercall_name, runstate_name, vmx_exit_reason_name, TRC_HVM_HYPERCALL, TRC_HVM_VMEXIT,
+        TRC_HVM_VMEXIT64, TRC_LOST_RECORDS, TRC_PV_HYPERCALL, TRC_PV_HYPERCALL64,
+        TRC_PV_HYPERCALL_SUBCALL, TRC_PV_HYPERCALL_V2, TRC_PV_HYPERCALL_V2_ARG_MASK,
+        TRC_SCHED_CONTINUE_RUNNING, TRC_SCHED_RUNSTATE_CHANGE, TRC_SCHED_RUNSTATE_MASK,
+    },
+};
+
+/// The value of a decoded field of an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Value {
+    Num(u64),
+    Str(&'static str),
+}
+
+/// Decodes the extra values of the known events into named fields.
+pub(crate) fn decode(event: &Event) -> Vec<(&'static str, Value)> {
+    let code = event.code.value();
+    let extra = &event.extra;
+    let mut fields = Vec::new();
+
+    fn push_domain(fields: &mut Vec<(&'static str, Value)>, value: Option<u32>) {
+        if let Some(domain) = value.map(Domain::from) {
+            fields.push(("dom", Value::Num(u16::from(domain.kind).into())));
+            fields.push(("vcpu", Value::Num(domain.vcpu.into())));
+        }
+    }
+
+    if code & !TRC_SCHED_RUNSTATE_MASK == TRC_SCHED_RUNSTATE_CHANGE {
+        push_domain(&mut fields, extra[0]);
+
+        for (name, state) in [
+            ("old_state", (code >> 8) & 0xF),
+            ("new_state", (code >> 4) & 0xF),
+        ] {
+            if let Some(state) = runstate_name(state) {
+                fields.push((name, Value::Str(state)));
+            }
+        }
+
+        return fields;
+    }
+
+    match code {
+        TRC_SCHED_CONTINUE_RUNNING => push_domain(&mut fields, extra[0]),
+        TRC_LOST_RECORDS => {
+            if let Some(count) = extra[0] {
+                fields.push(("lost_records", Value::Num(count.into())));
+            }
+        }
+        TRC_HVM_VMEXIT | TRC_HVM_VMEXIT64 => {
+            if let Some(reason) = extra[0] {
+                fields.push(("exit_reason", Value::Num(reason.into())));
+
+                if let Some(name) = vmx_exit_reason_name(reason) {
+                    fields.push(("exit_reason_name", Value::Str(name)));
+                }
+            }
+
+            let rip = match (code, extra[1], extra[2]) {
+                (TRC_HVM_VMEXIT64, Some(lo), Some(hi)) => Some(u64::from(hi) << 32 | u64::from(lo)),
+                (TRC_HVM_VMEXIT, Some(rip), _) => Some(rip.into()),
+                _ => None,
+            };
+
+            if let Some(rip) = rip {
+                fields.push(("rip", Value::Num(rip)));
+            }
+        }
+        TRC_HVM_HYPERCALL
+        | TRC_PV_HYPERCALL
+        | TRC_PV_HYPERCALL64
+        | TRC_PV_HYPERCALL_V2
+        | TRC_PV_HYPERCALL_SUBCALL => {
+            let op = match code {
+                TRC_HVM_HYPERCALL => extra[0],
+                // The operation follows the (32 or 64-bit) instruction pointer
+                TRC_PV_HYPERCALL | TRC_PV_HYPERCALL64 => extra.iter().rev().find_map(|e| *e),
+                _ => extra[0].map(|op| op & !TRC_PV_HYPERCALL_V2_ARG_MASK),
+            };
+
+            if let Some(op) = op {
+                fields.push(("hypercall", Value::Num(op.into())));
+
+                if let Some(name) = hypercall_name(op) {
+                    fields.push(("hypercall_name", Value::Str(name)));
+                }
+            }
+        }
+        _ => (),
+    }
+
+    fields
+}
+
+/// Returns the string value of the decoded field with the given name, if any.
+pub(crate) fn find_str(fields: &[(&'static str, Value)], name: &str) -> Option<&'static str> {
+    fields.iter().find_map(|(field, value)| match value {
+        Value::Str(value) if *field == name => Some(*value),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, Value};
+    use crate::record::{Event, EventCode, EVENT_EXTRA_CAPACITY};
+
+    fn event(code: u32, extra: &[u32]) -> Event {
+        let mut extras = [None; EVENT_EXTRA_CAPACITY];
+        extras
+            .iter_mut()
+            .zip(extra)
+            .for_each(|(e, v)| *e = Some(*v));
+
+        Event {
+            code: EventCode::from(code),
+            tsc: 0,
+            extra: extras,
+        }
+    }
+
+    #[test]
+    fn decode_test() {
+        assert_eq!(
+            decode(&event(0x00081102, &[48, 0x89ABCDEF, 0x01234567])),
+            [
+                ("exit_reason", Value::Num(48)),
+                ("exit_reason_name", Value::Str("EPT_VIOLATION")),
+                ("rip", Value::Num(0x0123456789ABCDEF)),
+            ]
+        );
+
+        assert_eq!(
+            decode(&event(0x00021121, &[0x00050001])),
+            [
+                ("dom", Value::Num(5)),
+                ("vcpu", Value::Num(1)),
+                ("old_state", Value::Str("runnable")),
+                ("new_state", Value::Str("blocked")),
+            ]
+        );
+
+        assert_eq!(
+            decode(&event(0x00082012, &[29])),
+            [
+                ("hypercall", Value::Num(29)),
+                ("hypercall_name", Value::Str("sched_op")),
+            ]
+        );
+
+        assert!(decode(&event(0x00081001, &[])).is_empty());
+    }
+}
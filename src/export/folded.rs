@@ -0,0 +1,226 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use fxhash::FxBuildHasher;
+
+use super::jsonl::tsc_to_ns;
+use crate::{
+    record::{Domain, DomainKind, Record},
+    trc::{vmx_exit_reason_name, TRC_HVM_VMENTRY, TRC_HVM_VMEXIT, TRC_HVM_VMEXIT64},
+    Error, Result,
+};
+
+/// The stack of a CPU: the domain it runs and the exit being handled, if any.
+type Stack = (u32, Domain, Option<u32>);
+
+/// The state of a CPU: its current stack and the timestamp it started at.
+#[derive(Debug)]
+struct CpuState {
+    domain: Domain,
+    exit: Option<u32>,
+    since_tsc: u64,
+}
+
+/// Writer of the "folded" stacks of records, as consumed by
+/// [`flamegraph.pl`](https://github.com/brendangregg/FlameGraph)
+/// and compatible tools, weighted by time.
+///
+/// The time of each CPU is split between the virtual CPUs it runs (from one
+/// change of the domain to the next) and, for HVM guests, the exits they
+/// cause (from a VMEXIT to the next VMENTRY), as in:
+///
+/// ```text
+/// cpu3;dom5;vcpu1 2345678
+/// cpu3;dom5;vcpu1;vmexit:EPT_VIOLATION 123456
+/// cpu3;idle 345678
+/// ```
+///
+/// Weights are TSC cycles, or nanoseconds if [`cpu_hz`](FoldedWriter::cpu_hz)
+/// is set. Stacks are aggregated over the records, so they are only written
+/// by [`finish`](FoldedWriter::finish).
+///
+/// **Note:** Records must be written in TSC order for each CPU, as they
+/// are found in a trace file or in a [`Trace`](crate::Trace).
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{export::FoldedWriter, RecordReader, Result};
+///
+/// fn main() -> Result<()> {
+///     let mut writer = FoldedWriter::create("/path/to/xentrace.folded")?;
+///
+///     for record in RecordReader::from_file("/path/to/xentrace.bin")? {
+///         writer.write_record(&record?)?;
+///     }
+///
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FoldedWriter<W: io::Write> {
+    wtr: W,
+    cpu_hz: Option<u64>,
+    cpus: HashMap<u32, CpuState, FxBuildHasher>,
+    stacks: HashMap<Stack, u64, FxBuildHasher>,
+}
+
+impl FoldedWriter<io::BufWriter<fs::File>> {
+    /// Constructs a `FoldedWriter` to a file specified by its path,
+    /// which is created (or truncated).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to create the file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        fs::File::create(path)
+            .map_err(|e| Error::io_error("Failed to create folded stacks file", e))
+            .map(io::BufWriter::new)
+            .map(Self::new)
+    }
+}
+
+impl<W: io::Write> FoldedWriter<W> {
+    /// Constructs a `FoldedWriter` to any type that implements `io::Write`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            wtr: writer,
+            cpu_hz: None,
+            cpus: HashMap::default(),
+            stacks: HashMap::default(),
+        }
+    }
+
+    /// Sets the frequency (in Hz) of the TSC of the traced host, used
+    /// to convert the weights of the stacks to nanoseconds.
+    pub fn cpu_hz(mut self, hz: u64) -> Self {
+        self.cpu_hz = (hz > 0).then_some(hz);
+        self
+    }
+
+    /// Accounts the time elapsed since the previous record of the same CPU.
+    ///
+    /// Never fails, as nothing is written before [`finish`](FoldedWriter::finish).
+    pub fn write_record(&mut self, record: &Record) -> Result<()> {
+        let tsc = record.event.tsc;
+        let code = record.event.code.value();
+
+        let state = self.cpus.entry(record.cpu).or_insert(CpuState {
+            domain: record.domain,
+            exit: None,
+            since_tsc: tsc,
+        });
+
+        let elapsed = tsc.saturating_sub(state.since_tsc);
+        if elapsed > 0 {
+            *self
+                .stacks
+                .entry((record.cpu, state.domain, state.exit))
+                .or_default() += elapsed;
+        }
+
+        if state.domain != record.domain {
+            state.domain = record.domain;
+            state.exit = None;
+        }
+
+        match code {
+            TRC_HVM_VMEXIT | TRC_HVM_VMEXIT64 => state.exit = record.event.extra[0],
+            TRC_HVM_VMENTRY => state.exit = None,
+            _ => (),
+        }
+
+        state.since_tsc = state.since_tsc.max(tsc);
+        Ok(())
+    }
+
+    /// Writes the stacks, sorted, then flushes and returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write
+    /// the stacks or to flush the writer.
+    pub fn finish(mut self) -> Result<W> {
+        let mut lines = self
+            .stacks
+            .iter()
+            .map(|(stack, cycles)| {
+                let weight = match self.cpu_hz {
+                    Some(hz) => tsc_to_ns(*cycles, hz),
+                    None => *cycles,
+                };
+
+                (format_stack(stack), weight)
+            })
+            .filter(|(_, weight)| *weight > 0)
+            .collect::<Vec<_>>();
+
+        lines.sort_unstable();
+
+        for (stack, weight) in lines {
+            writeln!(self.wtr, "{} {}", stack, weight)
+                .map_err(|e| Error::io_error("Failed to write folded stack", e))?;
+        }
+
+        self.wtr
+            .flush()
+            .map_err(|e| Error::io_error("Failed to flush folded stacks writer", e))?;
+
+        Ok(self.wtr)
+    }
+}
+
+fn format_stack((cpu, domain, exit): &Stack) -> String {
+    let mut stack = match domain.kind {
+        DomainKind::Idle => format!("cpu{};idle", cpu),
+        kind => format!("cpu{};{};vcpu{}", cpu, kind, domain.vcpu),
+    };
+
+    if let Some(reason) = exit {
+        match vmx_exit_reason_name(*reason) {
+            Some(name) => stack.push_str(&format!(";vmexit:{}", name)),
+            None => stack.push_str(&format!(";vmexit:{}", reason)),
+        }
+    }
+
+    stack
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FoldedWriter;
+    use crate::{
+        record::{Domain, DomainKind},
+        TraceBuilder,
+    };
+
+    #[test]
+    fn folded_test() {
+        let trace = TraceBuilder::new()
+            .cpu(3)
+            .schedule(1000, Domain::new(DomainKind::Guest(5), 1))
+            .event(2000, 0x00081102, &[48, 0x1000, 0])
+            .event(2500, 0x00081001, &[])
+            .event(3000, 0x00081002, &[1000, 0x1000])
+            .event(3100, 0x00081001, &[])
+            .schedule(4000, Domain::new(DomainKind::Idle, 0))
+            .event(6000, 0x0001F001, &[0])
+            .build()
+            .unwrap();
+
+        let mut writer = FoldedWriter::new(Vec::new());
+        trace
+            .iter()
+            .try_for_each(|r| writer.write_record(r))
+            .unwrap();
+        let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(
+            output,
+            "cpu3;dom5;vcpu1 2400\n\
+             cpu3;dom5;vcpu1;vmexit:1000 100\n\
+             cpu3;dom5;vcpu1;vmexit:EPT_VIOLATION 500\n\
+             cpu3;idle 2000\n"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::{fmt::Write as _, fs, io, path::Path};
+
+use super::decode::{decode, Value};
+use crate::{record::Record, Error, Result};
+
+/// Writer of records in the JSON Lines format, one JSON object per line.
+///
+/// Each object holds the `cpu`, the `domain` (*e.g.* `"dom0"` or `"idle"`)
+/// and `vcpu`, the `tsc`, the `time_ns` (see [`cpu_hz`](JsonLinesWriter::cpu_hz)),
+/// the event `code` and its `name` (`null` if unknown), the `extras` and the
+/// `fields` decoded from them for the known events (*e.g.* `exit_reason_name`
+/// of a VMEXIT), as in:
+///
+/// ```text
+/// {"cpu":2,"domain":"dom5","vcpu":1,"tsc":1234,"time_ns":null,"code":528642,"name":"VMEXIT64","extras":[48,4096,0],"fields":{"exit_reason":48,"exit_reason_name":"EPT_VIOLATION","rip":4096}}
+/// ```
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{export::JsonLinesWriter, RecordReader, Result};
+///
+/// fn main() -> Result<()> {
+///     let mut writer = JsonLinesWriter::create("/path/to/xentrace.jsonl")?.cpu_hz(2_400_000_000);
+///
+///     for record in RecordReader::from_file("/path/to/xentrace.bin")? {
+///         writer.write_record(&record?)?;
+///     }
+///
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct JsonLinesWriter<W: io::Write> {
+    wtr: W,
+    cpu_hz: Option<u64>,
+    line: String,
+}
+
+impl JsonLinesWriter<io::BufWriter<fs::File>> {
+    /// Constructs a `JsonLinesWriter` to a file specified by its path,
+    /// which is created (or truncated).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to create the file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        fs::File::create(path)
+            .map_err(|e| Error::io_error("Failed to create JSON Lines file", e))
+            .map(io::BufWriter::new)
+            .map(Self::new)
+    }
+}
+
+impl<W: io::Write> JsonLinesWriter<W> {
+    /// Constructs a `JsonLinesWriter` to any type that implements `io::Write`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            wtr: writer,
+            cpu_hz: None,
+            line: String::with_capacity(256),
+        }
+    }
+
+    /// Sets the frequency (in Hz) of the TSC of the traced host, used
+    /// to convert the TSC values to nanoseconds (`time_ns`).
+    ///
+    /// Without it, `time_ns` is always `null`.
+    pub fn cpu_hz(mut self, hz: u64) -> Self {
+        self.cpu_hz = (hz > 0).then_some(hz);
+        self
+    }
+
+    /// Writes a single record, as a line.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write the line.
+    pub fn write_record(&mut self, record: &Record) -> Result<()> {
+        // Writing to a "String" cannot fail
+        self.line.clear();
+        let _ = self.format_record(record);
+
+        self.wtr
+            .write_all(self.line.as_bytes())
+            .map_err(|e| Error::io_error("Failed to write JSON line", e))
+    }
+
+    /// Flushes the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to flush the writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.wtr
+            .flush()
+            .map_err(|e| Error::io_error("Failed to flush JSON Lines writer", e))
+    }
+
+    /// Flushes and returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to flush the writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.wtr)
+    }
+
+    // Strings are names of this crate, JSON-safe with no escaping
+    fn format_record(&mut self, record: &Record) -> std::fmt::Result {
+        let line = &mut self.line;
+        let event = &record.event;
+
+        write!(
+            line,
+            r#"{{"cpu":{},"domain":"{}","vcpu":{},"tsc":{},"time_ns":"#,
+            record.cpu, record.domain.kind, record.domain.vcpu, event.tsc
+        )?;
+
+        match self.cpu_hz {
+            Some(hz) => write!(line, "{}", tsc_to_ns(event.tsc, hz))?,
+            None => line.push_str("null"),
+        }
+
+        write!(line, r#","code":{},"name":"#, event.code.value())?;
+        match event.code.name() {
+            Some(name) => write!(line, r#""{}""#, name)?,
+            None => line.push_str("null"),
+        }
+
+        line.push_str(r#","extras":["#);
+        for (i, value) in event.extra.iter().map_while(|e| *e).enumerate() {
+            let sep = if i > 0 { "," } else { "" };
+            write!(line, "{}{}", sep, value)?;
+        }
+
+        line.push_str(r#"],"fields":{"#);
+        for (i, (name, value)) in decode(event).into_iter().enumerate() {
+            let sep = if i > 0 { "," } else { "" };
+            match value {
+                Value::Num(value) => write!(line, r#"{}"{}":{}"#, sep, name, value)?,
+                Value::Str(value) => write!(line, r#"{}"{}":"{}""#, sep, name, value)?,
+            }
+        }
+
+        line.push_str("}}\n");
+        Ok(())
+    }
+}
+
+/// Converts a TSC value to nanoseconds, given the TSC frequency (in Hz).
+pub(crate) fn tsc_to_ns(tsc: u64, hz: u64) -> u64 {
+    (u128::from(tsc) * 1_000_000_000 / u128::from(hz)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonLinesWriter;
+    use crate::{
+        record::{Domain, DomainKind},
+        TraceBuilder,
+    };
+
+    #[test]
+    fn jsonl_test() {
+        let trace = TraceBuilder::new()
+            .cpu(2)
+            .schedule(1000, Domain::new(DomainKind::Guest(5), 1))
+            .event(2000, 0x00081102, &[48, 0x1000, 0])
+            .event(3000, 0x00081FFF, &[])
+            .build()
+            .unwrap();
+
+        let mut writer = JsonLinesWriter::new(Vec::new()).cpu_hz(1_000_000);
+        trace
+            .iter()
+            .try_for_each(|r| writer.write_record(r))
+            .unwrap();
+        let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            r#"{"cpu":2,"domain":"dom5","vcpu":1,"tsc":1000,"time_ns":1000000,"code":135425,"name":"runnable_to_running","extras":[327681],"fields":{"dom":5,"vcpu":1,"old_state":"runnable","new_state":"running"}}"#
+        );
+        assert_eq!(
+            lines[1],
+            r#"{"cpu":2,"domain":"dom5","vcpu":1,"tsc":2000,"time_ns":2000000,"code":528642,"name":"VMEXIT64","extras":[48,4096,0],"fields":{"exit_reason":48,"exit_reason_name":"EPT_VIOLATION","rip":4096}}"#
+        );
+        assert_eq!(
+            lines[2],
+            r#"{"cpu":2,"domain":"dom5","vcpu":1,"tsc":3000,"time_ns":3000000,"code":532479,"name":null,"extras":[],"fields":{}}"#
+        );
+    }
+}
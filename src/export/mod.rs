@@ -0,0 +1,33 @@
+//! Exporters of the [`Record`](crate::record::Record)s of a trace to other formats.
+//!
+//! Exporters write one record at a time to any type that implements `io::Write`,
+//! so they can be fed by a [`RecordReader`](crate::RecordReader) as well as by
+//! a [`Trace`](crate::Trace).
+
+#[cfg(feature = "arrow")]
+mod arrow;
+mod chrome;
+mod csv;
+mod ctf;
+mod decode;
+mod folded;
+mod jsonl;
+mod perfetto;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod text;
+
+pub use self::{
+    chrome::ChromeTraceWriter,
+    csv::{Column, CsvWriter},
+    ctf::CtfWriter,
+    folded::FoldedWriter,
+    jsonl::JsonLinesWriter,
+    perfetto::PerfettoWriter,
+    text::{FormattedRecord, XentraceFormat},
+};
+
+#[cfg(feature = "arrow")]
+pub use self::arrow::{record_batch, schema, ParquetWriter};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteWriter;
@@ -0,0 +1,477 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use fxhash::FxBuildHasher;
+
+use super::{
+    decode::{decode, find_str},
+    jsonl::tsc_to_ns,
+};
+use crate::{
+    record::{Domain, DomainKind, Record},
+    trc::{TRC_HVM_VMENTRY, TRC_HVM_VMEXIT, TRC_HVM_VMEXIT64},
+    Error, Result,
+};
+
+// Field numbers of the Perfetto protos (perfetto/trace/...)
+const TRACE_PACKET: u32 = 1;
+const PACKET_TIMESTAMP: u32 = 8;
+const PACKET_SEQUENCE_ID: u32 = 10;
+const PACKET_TRACK_EVENT: u32 = 11;
+const PACKET_SEQUENCE_FLAGS: u32 = 13;
+const PACKET_TRACK_DESCRIPTOR: u32 = 60;
+const TRACK_UUID: u32 = 1;
+const TRACK_NAME: u32 = 2;
+const TRACK_COUNTER: u32 = 8;
+const EVENT_TYPE: u32 = 9;
+const EVENT_TRACK_UUID: u32 = 11;
+const EVENT_NAME: u32 = 23;
+const EVENT_COUNTER_VALUE: u32 = 30;
+
+// TrackEvent.Type values
+const TYPE_SLICE_BEGIN: u64 = 1;
+const TYPE_SLICE_END: u64 = 2;
+const TYPE_COUNTER: u64 = 4;
+
+/// TracePacket.SequenceFlags.SEQ_INCREMENTAL_STATE_CLEARED
+const SEQ_INCREMENTAL_STATE_CLEARED: u64 = 1;
+
+/// The (only) sequence of the written packets.
+const SEQUENCE_ID: u64 = 1;
+
+/// The uuid of the counter track of the busy CPUs, the ones
+/// of the CPU tracks being the processor ids plus one.
+const BUSY_TRACK_UUID: u64 = 1 << 32;
+
+/// The state of the slices of a CPU track.
+#[derive(Debug)]
+struct CpuTrack {
+    domain: Domain,
+    exit: bool,
+    last_ns: u64,
+}
+
+/// Writer of records in the native Perfetto protobuf format (a `Trace`
+/// of `TracePacket`s), as opened by [Perfetto](https://ui.perfetto.dev).
+///
+/// Each physical CPU is a track, on which:
+/// - the intervals during which a domain other than the idle one is running
+///   are slices named after the virtual CPU (*e.g.* `dom5 vcpu1`), except
+///   for the records preceding the first scheduling event of the CPU (of the
+///   [default](DomainKind::Default) domain), whose domain is unknown;
+/// - the time from a VMEXIT to the next VMENTRY is a slice (nested in the one
+///   of the virtual CPU) named after the exit reason (*e.g.* `VMEXIT EPT_VIOLATION`).
+///
+/// The `busy pCPUs` counter track holds the count of CPUs running such domains.
+///
+/// Timestamps are converted to nanoseconds using the frequency set with
+/// [`cpu_hz`](PerfettoWriter::cpu_hz), otherwise TSC values are used as
+/// nanoseconds.
+///
+/// **Note:** Records must be written in TSC order, as they are found
+/// in a [`Trace`](crate::Trace).
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{export::PerfettoWriter, Result, Trace};
+///
+/// fn main() -> Result<()> {
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///     let mut writer = PerfettoWriter::create("/path/to/xentrace.pftrace")?.cpu_hz(2_400_000_000);
+///
+///     for record in trace.iter() {
+///         writer.write_record(record)?;
+///     }
+///
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct PerfettoWriter<W: io::Write> {
+    wtr: W,
+    cpu_hz: Option<u64>,
+    cpus: HashMap<u32, CpuTrack, FxBuildHasher>,
+    busy: i64,
+    packet: Message,
+    buf: Vec<u8>,
+}
+
+impl PerfettoWriter<io::BufWriter<fs::File>> {
+    /// Constructs a `PerfettoWriter` to a file specified by its path,
+    /// which is created (or truncated).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to create the file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        fs::File::create(path)
+            .map_err(|e| Error::io_error("Failed to create Perfetto trace file", e))
+            .map(io::BufWriter::new)
+            .map(Self::new)
+    }
+}
+
+impl<W: io::Write> PerfettoWriter<W> {
+    /// Constructs a `PerfettoWriter` to any type that implements `io::Write`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            wtr: writer,
+            cpu_hz: None,
+            cpus: HashMap::default(),
+            busy: 0,
+            packet: Message::default(),
+            buf: Vec::with_capacity(64),
+        }
+    }
+
+    /// Sets the frequency (in Hz) of the TSC of the traced host, used
+    /// to convert the TSC values to nanoseconds.
+    pub fn cpu_hz(mut self, hz: u64) -> Self {
+        self.cpu_hz = (hz > 0).then_some(hz);
+        self
+    }
+
+    /// Writes the packets of a single record, if any.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write the packets.
+    pub fn write_record(&mut self, record: &Record) -> Result<()> {
+        let ns = match self.cpu_hz {
+            Some(hz) => tsc_to_ns(record.event.tsc, hz),
+            None => record.event.tsc,
+        };
+
+        if self.cpus.is_empty() {
+            self.write_descriptor(BUSY_TRACK_UUID, "busy pCPUs", true)?;
+        }
+
+        let track = cpu_uuid(record.cpu);
+        let (domain, exit) = match self.cpus.get_mut(&record.cpu) {
+            Some(cpu) => {
+                cpu.last_ns = cpu.last_ns.max(ns);
+                (Some(cpu.domain), cpu.exit)
+            }
+            None => {
+                self.write_descriptor(track, &format!("pCPU {}", record.cpu), false)?;
+                self.cpus.insert(
+                    record.cpu,
+                    CpuTrack {
+                        domain: record.domain,
+                        exit: false,
+                        last_ns: ns,
+                    },
+                );
+                (None, false)
+            }
+        };
+
+        let code = record.event.code.value();
+        let mut exit_open = exit;
+
+        if exit_open && (domain != Some(record.domain) || is_exit_boundary(code)) {
+            self.write_event(ns, track, TYPE_SLICE_END, None)?;
+            exit_open = false;
+        }
+
+        if domain != Some(record.domain) {
+            let mut busy = self.busy;
+
+            if domain.as_ref().map_or(false, is_busy) {
+                self.write_event(ns, track, TYPE_SLICE_END, None)?;
+                busy -= 1;
+            }
+
+            if is_busy(&record.domain) {
+                let name = format!("{} vcpu{}", record.domain.kind, record.domain.vcpu);
+                self.write_event(ns, track, TYPE_SLICE_BEGIN, Some(&name))?;
+                busy += 1;
+            }
+
+            if busy != self.busy {
+                self.busy = busy;
+                self.write_counter(ns, busy)?;
+            }
+        }
+
+        if matches!(code, TRC_HVM_VMEXIT | TRC_HVM_VMEXIT64) {
+            let fields = decode(&record.event);
+            let name = match find_str(&fields, "exit_reason_name") {
+                Some(reason) => format!("VMEXIT {}", reason),
+                None => "VMEXIT".to_string(),
+            };
+
+            self.write_event(ns, track, TYPE_SLICE_BEGIN, Some(&name))?;
+            exit_open = true;
+        }
+
+        if let Some(cpu) = self.cpus.get_mut(&record.cpu) {
+            cpu.domain = record.domain;
+            cpu.exit = exit_open;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to flush the writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.wtr
+            .flush()
+            .map_err(|e| Error::io_error("Failed to flush Perfetto writer", e))
+    }
+
+    /// Ends the open slices (at the last record of their CPU),
+    /// then flushes and returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write the
+    /// last packets or to flush the writer.
+    pub fn finish(mut self) -> Result<W> {
+        let mut cpus = std::mem::take(&mut self.cpus)
+            .into_iter()
+            .collect::<Vec<_>>();
+        cpus.sort_unstable_by_key(|(cpu, _)| *cpu);
+
+        for (cpu, track) in cpus {
+            if track.exit {
+                self.write_event(track.last_ns, cpu_uuid(cpu), TYPE_SLICE_END, None)?;
+            }
+            if is_busy(&track.domain) {
+                self.write_event(track.last_ns, cpu_uuid(cpu), TYPE_SLICE_END, None)?;
+            }
+        }
+
+        self.flush()?;
+        Ok(self.wtr)
+    }
+
+    fn write_descriptor(&mut self, uuid: u64, name: &str, counter: bool) -> Result<()> {
+        let mut descriptor = Message::default();
+        descriptor.varint(TRACK_UUID, uuid);
+        descriptor.bytes(TRACK_NAME, name.as_bytes());
+        if counter {
+            descriptor.bytes(TRACK_COUNTER, &[]);
+        }
+
+        self.packet.clear();
+        // The first packet of the sequence
+        if uuid == BUSY_TRACK_UUID {
+            self.packet
+                .varint(PACKET_SEQUENCE_FLAGS, SEQ_INCREMENTAL_STATE_CLEARED);
+        }
+        self.packet.varint(PACKET_SEQUENCE_ID, SEQUENCE_ID);
+        self.packet.message(PACKET_TRACK_DESCRIPTOR, &descriptor);
+        self.write_packet()
+    }
+
+    fn write_event(&mut self, ns: u64, track: u64, kind: u64, name: Option<&str>) -> Result<()> {
+        let mut event = Message::default();
+        event.varint(EVENT_TYPE, kind);
+        event.varint(EVENT_TRACK_UUID, track);
+        if let Some(name) = name {
+            event.bytes(EVENT_NAME, name.as_bytes());
+        }
+
+        self.write_event_packet(ns, &event)
+    }
+
+    fn write_counter(&mut self, ns: u64, value: i64) -> Result<()> {
+        let mut event = Message::default();
+        event.varint(EVENT_TYPE, TYPE_COUNTER);
+        event.varint(EVENT_TRACK_UUID, BUSY_TRACK_UUID);
+        event.varint(EVENT_COUNTER_VALUE, value as u64);
+
+        self.write_event_packet(ns, &event)
+    }
+
+    fn write_event_packet(&mut self, ns: u64, event: &Message) -> Result<()> {
+        self.packet.clear();
+        self.packet.varint(PACKET_TIMESTAMP, ns);
+        self.packet.varint(PACKET_SEQUENCE_ID, SEQUENCE_ID);
+        self.packet.message(PACKET_TRACK_EVENT, event);
+        self.write_packet()
+    }
+
+    fn write_packet(&mut self) -> Result<()> {
+        self.buf.clear();
+        put_key(&mut self.buf, TRACE_PACKET, WIRE_LEN);
+        put_varint(&mut self.buf, self.packet.0.len() as u64);
+
+        self.wtr
+            .write_all(&self.buf)
+            .and_then(|_| self.wtr.write_all(&self.packet.0))
+            .map_err(|e| Error::io_error("Failed to write Perfetto packet", e))
+    }
+}
+
+fn cpu_uuid(cpu: u32) -> u64 {
+    u64::from(cpu) + 1
+}
+
+fn is_busy(domain: &Domain) -> bool {
+    !matches!(domain.kind, DomainKind::Idle | DomainKind::Default)
+}
+
+fn is_exit_boundary(code: u32) -> bool {
+    matches!(code, TRC_HVM_VMENTRY | TRC_HVM_VMEXIT | TRC_HVM_VMEXIT64)
+}
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_LEN: u32 = 2;
+
+/// A protobuf message, encoded field by field.
+#[derive(Debug, Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn varint(&mut self, field: u32, value: u64) {
+        put_key(&mut self.0, field, WIRE_VARINT);
+        put_varint(&mut self.0, value);
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        put_key(&mut self.0, field, WIRE_LEN);
+        put_varint(&mut self.0, value.len() as u64);
+        self.0.extend_from_slice(value);
+    }
+
+    fn message(&mut self, field: u32, value: &Message) {
+        self.bytes(field, &value.0);
+    }
+}
+
+fn put_key(buf: &mut Vec<u8>, field: u32, wire_type: u32) {
+    put_varint(buf, u64::from(field << 3 | wire_type));
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{put_varint, PerfettoWriter};
+    use crate::{
+        record::{Domain, DomainKind},
+        TraceBuilder,
+    };
+
+    fn get_varint(data: &mut &[u8]) -> u64 {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = data[0];
+            *data = &data[1..];
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte < 0x80 {
+                break;
+            }
+        }
+        value
+    }
+
+    /// Decodes the (field, value) pairs of a message, nested messages as bytes.
+    fn fields(mut data: &[u8]) -> Vec<(u64, Result<u64, Vec<u8>>)> {
+        let mut fields = Vec::new();
+        while !data.is_empty() {
+            let key = get_varint(&mut data);
+            let value = match key & 7 {
+                0 => Ok(get_varint(&mut data)),
+                2 => {
+                    let len = get_varint(&mut data) as usize;
+                    let (value, rest) = data.split_at(len);
+                    data = rest;
+                    Err(value.to_vec())
+                }
+                _ => unreachable!(),
+            };
+            fields.push((key >> 3, value));
+        }
+        fields
+    }
+
+    #[test]
+    fn varint_test() {
+        let mut buf = Vec::new();
+        put_varint(&mut buf, 300);
+        assert_eq!(buf, [0xAC, 0x02]);
+        assert_eq!(get_varint(&mut buf.as_slice()), 300);
+    }
+
+    #[test]
+    fn perfetto_test() {
+        let trace = TraceBuilder::new()
+            .cpu(1)
+            .event(500, 0x00081001, &[]) // Unknown domain, not busy
+            .schedule(1000, Domain::new(DomainKind::Guest(5), 1))
+            .event(2000, 0x00081102, &[48, 0x1000, 0])
+            .event(3000, 0x00081001, &[])
+            .schedule(5000, Domain::new(DomainKind::Idle, 0))
+            .build()
+            .unwrap();
+
+        let mut writer = PerfettoWriter::new(Vec::new());
+        trace
+            .iter()
+            .try_for_each(|r| writer.write_record(r))
+            .unwrap();
+        let output = writer.finish().unwrap();
+
+        let packets = fields(&output)
+            .into_iter()
+            .map(|(field, packet)| {
+                assert_eq!(field, 1);
+                fields(&packet.unwrap_err())
+            })
+            .collect::<Vec<_>>();
+
+        // Busy counter and pCPU 1 descriptors
+        assert_eq!(packets.len(), 2 + 6);
+        let descriptor = fields(packets[1][1].1.as_ref().unwrap_err());
+        assert_eq!(descriptor[0], (1, Ok(2)));
+        assert_eq!(descriptor[1], (2, Err(b"pCPU 1".to_vec())));
+
+        // (timestamp, type, name) of the events
+        let events = packets[2..]
+            .iter()
+            .map(|packet| {
+                assert_eq!(packet[1], (10, Ok(1)));
+                let event = fields(packet[2].1.as_ref().unwrap_err());
+                let name = event
+                    .iter()
+                    .find(|(field, _)| *field == 23)
+                    .map(|(_, name)| String::from_utf8(name.clone().unwrap_err()).unwrap());
+                (
+                    packet[0].1.clone().unwrap(),
+                    event[0].1.clone().unwrap(),
+                    name,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            [
+                (1000, 1, Some("dom5 vcpu1".to_string())),
+                (1000, 4, None),
+                (2000, 1, Some("VMEXIT EPT_VIOLATION".to_string())),
+                (3000, 2, None),
+                (5000, 2, None),
+                (5000, 4, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use fxhash::FxBuildHasher;
+use rusqlite::{params, Connection};
+
+use crate::{
+    analysis::OccupancyAnalyzer,
+    record::{Domain, DomainKind, Record},
+    trc::{runstate_name, TRC_SCHED_RUNSTATE_CHANGE, TRC_SCHED_RUNSTATE_MASK},
+    Error, Result,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE records (
+    id INTEGER PRIMARY KEY,
+    cpu INTEGER NOT NULL,
+    domid INTEGER NOT NULL,
+    vcpu INTEGER NOT NULL,
+    tsc INTEGER NOT NULL,
+    code INTEGER NOT NULL,
+    name TEXT,
+    extra0 INTEGER,
+    extra1 INTEGER,
+    extra2 INTEGER,
+    extra3 INTEGER,
+    extra4 INTEGER,
+    extra5 INTEGER,
+    extra6 INTEGER
+);
+
+CREATE TABLE domains (
+    domid INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    vcpus INTEGER NOT NULL,
+    records INTEGER NOT NULL,
+    first_tsc INTEGER NOT NULL,
+    last_tsc INTEGER NOT NULL
+);
+
+CREATE TABLE runstates (
+    domid INTEGER NOT NULL,
+    vcpu INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    start_tsc INTEGER NOT NULL,
+    end_tsc INTEGER NOT NULL,
+    duration INTEGER NOT NULL
+);
+
+CREATE TABLE occupancy (
+    cpu INTEGER NOT NULL,
+    domid INTEGER NOT NULL,
+    vcpu INTEGER NOT NULL,
+    start_tsc INTEGER NOT NULL,
+    end_tsc INTEGER NOT NULL,
+    duration INTEGER NOT NULL
+);
+";
+
+// Created once the records are inserted, which is faster
+const INDICES: &str = "
+CREATE INDEX records_tsc ON records (tsc);
+CREATE INDEX records_cpu ON records (cpu, tsc);
+CREATE INDEX records_domain ON records (domid, vcpu, tsc);
+CREATE INDEX records_code ON records (code);
+CREATE INDEX runstates_domain ON runstates (domid, vcpu, start_tsc);
+CREATE INDEX runstates_state ON runstates (state);
+CREATE INDEX occupancy_cpu ON occupancy (cpu, start_tsc);
+CREATE INDEX occupancy_domain ON occupancy (domid, vcpu, start_tsc);
+";
+
+const INSERT_RECORD: &str = "INSERT INTO records (cpu, domid, vcpu, tsc, code, name, extra0, \
+    extra1, extra2, extra3, extra4, extra5, extra6) \
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)";
+
+const INSERT_RUNSTATE: &str = "INSERT INTO runstates (domid, vcpu, state, start_tsc, end_tsc, \
+    duration) VALUES (?1, ?2, ?3, ?4, ?5, ?6)";
+
+/// The statistics of the records of a domain.
+#[derive(Debug)]
+struct DomainData {
+    vcpus: HashSet<u16, FxBuildHasher>,
+    records: u64,
+    first_tsc: u64,
+    last_tsc: u64,
+}
+
+/// Writer of records to a SQLite database, for ad-hoc SQL queries.
+///
+/// The database holds the following tables:
+/// - `records`: the records (`cpu`, `domid`, `vcpu`, `tsc`, `code`,
+///   the event `name` and the `extra0` to `extra6` values, or `NULL`);
+/// - `domains`: for each domain (`domid`), its `name` (*e.g.* `dom5`
+///   or `idle`), the count of `vcpus` and of `records`, the `first_tsc`
+///   and the `last_tsc` of the records;
+/// - `runstates`: the time intervals (`start_tsc`, `end_tsc` and `duration`)
+///   spent by the virtual CPUs (`domid`, `vcpu`) in each runstate
+///   (`state`, *e.g.* `running` or `blocked`);
+/// - `occupancy`: the time intervals during which the physical CPUs (`cpu`)
+///   have been occupied by the virtual CPUs (see [`Occupancy`](crate::analysis::Occupancy)).
+///
+/// Timestamps and durations are TSC cycles, stored as (signed) 64-bit integers.
+/// The derived tables and the indices are written by [`finish`](SqliteWriter::finish),
+/// all in a single transaction.
+///
+/// **Note:** Records must be written in TSC order for each CPU, as they
+/// are found in a trace file or in a [`Trace`](crate::Trace).
+///
+/// *Available with the `sqlite` feature.*
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{export::SqliteWriter, RecordReader, Result};
+///
+/// fn main() -> Result<()> {
+///     let mut writer = SqliteWriter::create("/path/to/xentrace.db")?;
+///
+///     for record in RecordReader::from_file("/path/to/xentrace.bin")? {
+///         writer.write_record(&record?)?;
+///     }
+///
+///     // SELECT state, SUM(duration) FROM runstates WHERE domid = 5 GROUP BY state;
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SqliteWriter {
+    conn: Connection,
+    domains: HashMap<u16, DomainData, FxBuildHasher>,
+    runstates: HashMap<Domain, (u32, u64), FxBuildHasher>,
+    occupancy: OccupancyAnalyzer,
+    last_tsc: u64,
+}
+
+impl SqliteWriter {
+    /// Constructs a `SqliteWriter` to a database file specified by its path,
+    /// which is created (or truncated).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to create the file
+    /// or the tables of the database.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        fs::File::create(path).map_err(|e| Error::io_error("Failed to create SQLite file", e))?;
+
+        Connection::open(path)
+            .map_err(|e| sqlite_error("Failed to open SQLite database", e))
+            .and_then(Self::new)
+    }
+
+    /// Constructs a `SqliteWriter` to an open (empty) database.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to create
+    /// the tables of the database.
+    pub fn new(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)
+            .and_then(|_| conn.execute_batch("BEGIN"))
+            .map_err(|e| sqlite_error("Failed to create SQLite tables", e))?;
+
+        Ok(Self {
+            conn,
+            domains: HashMap::default(),
+            runstates: HashMap::default(),
+            occupancy: OccupancyAnalyzer::new(),
+            last_tsc: 0,
+        })
+    }
+
+    /// Writes a single record, as a row of the `records` table.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to insert a row.
+    pub fn write_record(&mut self, record: &Record) -> Result<()> {
+        let event = &record.event;
+        let code = event.code.value();
+        let tsc = event.tsc;
+        let domid = u16::from(record.domain.kind);
+        let extra = &event.extra;
+
+        self.conn
+            .prepare_cached(INSERT_RECORD)
+            .and_then(|mut stmt| {
+                stmt.execute(params![
+                    record.cpu,
+                    domid,
+                    record.domain.vcpu,
+                    tsc as i64,
+                    code,
+                    event.code.name(),
+                    extra[0],
+                    extra[1],
+                    extra[2],
+                    extra[3],
+                    extra[4],
+                    extra[5],
+                    extra[6],
+                ])
+            })
+            .map_err(|e| sqlite_error("Failed to insert record", e))?;
+
+        self.last_tsc = self.last_tsc.max(tsc);
+        self.occupancy.push(record);
+
+        let data = self.domains.entry(domid).or_insert_with(|| DomainData {
+            vcpus: HashSet::default(),
+            records: 0,
+            first_tsc: tsc,
+            last_tsc: tsc,
+        });
+        data.vcpus.insert(record.domain.vcpu);
+        data.records += 1;
+        data.first_tsc = data.first_tsc.min(tsc);
+        data.last_tsc = data.last_tsc.max(tsc);
+
+        if code & !TRC_SCHED_RUNSTATE_MASK == TRC_SCHED_RUNSTATE_CHANGE {
+            let new = (code >> 4) & 0xF;
+            let domain = Domain::from(extra[0].unwrap_or(0));
+
+            // The interval of the old state is known only after the first change
+            if let Some((old, since)) = self.runstates.insert(domain, (new, tsc)) {
+                self.insert_runstate(domain, old, since, tsc)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the `domains`, `runstates` and `occupancy` tables and the indices,
+    /// then commits the transaction and returns the database connection.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to insert a row,
+    /// to create the indices or to commit the transaction.
+    pub fn finish(mut self) -> Result<Connection> {
+        // The current states last up to the end of the trace
+        let mut runstates = std::mem::take(&mut self.runstates)
+            .into_iter()
+            .collect::<Vec<_>>();
+        runstates.sort_unstable_by_key(|(domain, _)| (u16::from(domain.kind), domain.vcpu));
+
+        for (domain, (state, since)) in runstates {
+            self.insert_runstate(domain, state, since, self.last_tsc.max(since))?;
+        }
+
+        let mut domains = self.domains.iter().collect::<Vec<_>>();
+        domains.sort_unstable_by_key(|(domid, _)| **domid);
+
+        for (domid, data) in domains {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO domains (domid, name, vcpus, records, first_tsc, last_tsc) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .and_then(|mut stmt| {
+                    stmt.execute(params![
+                        domid,
+                        DomainKind::from(*domid).to_string(),
+                        data.vcpus.len() as i64,
+                        data.records as i64,
+                        data.first_tsc as i64,
+                        data.last_tsc as i64,
+                    ])
+                })
+                .map_err(|e| sqlite_error("Failed to insert domain", e))?;
+        }
+
+        let occupancy = std::mem::take(&mut self.occupancy).finish();
+        for cpu in occupancy.cpus() {
+            for interval in cpu.intervals() {
+                let domain = interval.domain();
+
+                self.conn
+                    .prepare_cached(
+                        "INSERT INTO occupancy (cpu, domid, vcpu, start_tsc, end_tsc, duration) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    )
+                    .and_then(|mut stmt| {
+                        stmt.execute(params![
+                            cpu.cpu(),
+                            u16::from(domain.kind),
+                            domain.vcpu,
+                            interval.start_tsc() as i64,
+                            interval.end_tsc() as i64,
+                            interval.duration() as i64,
+                        ])
+                    })
+                    .map_err(|e| sqlite_error("Failed to insert occupancy interval", e))?;
+            }
+        }
+
+        self.conn
+            .execute_batch(INDICES)
+            .and_then(|_| self.conn.execute_batch("COMMIT"))
+            .map_err(|e| sqlite_error("Failed to commit SQLite database", e))?;
+
+        Ok(self.conn)
+    }
+
+    fn insert_runstate(&self, domain: Domain, state: u32, start: u64, end: u64) -> Result<()> {
+        let state = runstate_name(state).unwrap_or("unknown");
+
+        self.conn
+            .prepare_cached(INSERT_RUNSTATE)
+            .and_then(|mut stmt| {
+                stmt.execute(params![
+                    u16::from(domain.kind),
+                    domain.vcpu,
+                    state,
+                    start as i64,
+                    end as i64,
+                    end.saturating_sub(start) as i64,
+                ])
+            })
+            .map(|_| ())
+            .map_err(|e| sqlite_error("Failed to insert runstate interval", e))
+    }
+}
+
+fn sqlite_error(msg: &str, e: rusqlite::Error) -> Error {
+    Error::new(format_args!("{}: {}", msg, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::SqliteWriter;
+    use crate::{
+        record::{Domain, DomainKind},
+        TraceBuilder,
+    };
+
+    #[test]
+    fn sqlite_test() {
+        let trace = TraceBuilder::new()
+            .cpu(1)
+            .schedule(1000, Domain::new(DomainKind::Guest(5), 1))
+            .event(2000, 0x00081102, &[48, 0x1000, 0])
+            .schedule(5000, Domain::new(DomainKind::Idle, 0))
+            .event(6000, 0x00081FFF, &[])
+            .build()
+            .unwrap();
+
+        let mut writer = SqliteWriter::new(Connection::open_in_memory().unwrap()).unwrap();
+        trace
+            .iter()
+            .try_for_each(|r| writer.write_record(r))
+            .unwrap();
+        let conn = writer.finish().unwrap();
+
+        let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+
+        assert_eq!(count("SELECT COUNT(*) FROM records"), trace.len() as i64);
+        assert_eq!(
+            count("SELECT extra1 FROM records WHERE name = 'VMEXIT64'"),
+            0x1000
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM records WHERE extra0 IS NULL"),
+            1
+        );
+
+        let name = conn
+            .query_row("SELECT name FROM domains WHERE domid = 5", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .unwrap();
+        assert_eq!(name, "dom5");
+
+        assert_eq!(
+            count("SELECT duration FROM occupancy WHERE domid = 5 AND vcpu = 1"),
+            4000
+        );
+        assert_eq!(
+            count(
+                "SELECT SUM(duration) FROM runstates \
+                 WHERE domid = 5 AND vcpu = 1 AND state = 'running'"
+            ),
+            4000
+        );
+        assert_eq!(
+            count("SELECT SUM(duration) FROM runstates WHERE domid = 5 AND state = 'runnable'"),
+            1000
+        );
+    }
+}
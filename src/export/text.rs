@@ -0,0 +1,139 @@
+use std::{collections::HashMap, fmt};
+
+use fxhash::FxBuildHasher;
+
+use crate::record::Record;
+
+/// The count of extra values printed by the default line of the `formats` file.
+const EXTRA_SLOTS: usize = 5;
+
+/// Formatter of records in the layout of the default line of the `formats`
+/// file of the `xentrace_format` tool, *e.g.*:
+///
+/// ```text
+/// CPU2  1234567890 (+    1200)  unknown (0x000000000008ffff)  [ 0x00000030 0x00001000 0x00000000 0x00000000 0x00000000 ]
+/// ```
+///
+/// The relative TSC (`+reltsc`) is the time elapsed since the previous record
+/// of the same CPU, `0` for the first one, so records must be formatted in
+/// TSC order for each CPU, as they are found in a trace file or in a
+/// [`Trace`](crate::Trace). The first 5 extra values are always printed,
+/// missing ones as `0x00000000`.
+///
+/// **Note:** Only the lines of unknown events match the ones of `xentrace_format`.
+/// The per-event templates of the `formats` file are not implemented: known
+/// events are printed with the default layout, named after
+/// [`EventCode::name`](crate::record::EventCode::name) (*e.g.* `VMEXIT64`).
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{export::XentraceFormat, RecordReader, Result};
+///
+/// fn main() -> Result<()> {
+///     let mut format = XentraceFormat::new();
+///
+///     for record in RecordReader::from_file("/path/to/xentrace.bin")? {
+///         println!("{}", format.display(&record?));
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct XentraceFormat {
+    last_tsc: HashMap<u32, u64, FxBuildHasher>,
+}
+
+impl XentraceFormat {
+    /// Constructs a new `XentraceFormat`, with no previous record.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns an object that implements `Display` for the record,
+    /// updating the previous record of its CPU.
+    pub fn display<'a>(&mut self, record: &'a Record) -> FormattedRecord<'a> {
+        let tsc = record.event.tsc;
+        let reltsc = match self.last_tsc.insert(record.cpu, tsc) {
+            Some(last_tsc) => tsc.saturating_sub(last_tsc),
+            None => 0,
+        };
+
+        FormattedRecord { record, reltsc }
+    }
+}
+
+/// A [`Record`] formatted as a line of `xentrace_format`.
+///
+/// This struct is created by [`XentraceFormat::display`].
+#[derive(Clone, Copy, Debug)]
+pub struct FormattedRecord<'a> {
+    record: &'a Record,
+    reltsc: u64,
+}
+
+impl FormattedRecord<'_> {
+    /// Returns the time elapsed since the previous record of the same CPU.
+    pub fn reltsc(&self) -> u64 {
+        self.reltsc
+    }
+}
+
+impl fmt::Display for FormattedRecord<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let event = &self.record.event;
+
+        write!(
+            f,
+            "CPU{}  {} (+{:8})  ",
+            self.record.cpu, event.tsc, self.reltsc
+        )?;
+
+        match event.code.name() {
+            Some(name) => write!(f, "{}  [", name)?,
+            None => write!(f, "unknown (0x{:016x})  [", event.code.value())?,
+        }
+
+        for value in event.extra.iter().take(EXTRA_SLOTS) {
+            write!(f, " 0x{:08x}", value.unwrap_or(0))?;
+        }
+
+        f.write_str(" ]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::XentraceFormat;
+    use crate::TraceBuilder;
+
+    #[test]
+    fn format_test() {
+        let trace = TraceBuilder::new()
+            .cpu(2)
+            .event(1000, 0x00081102, &[48, 0x1000, 0])
+            .cpu(0)
+            .event(1500, 0x00081001, &[])
+            .cpu(2)
+            .event(2200, 0x0008FFFF, &[1])
+            .build()
+            .unwrap();
+
+        let mut format = XentraceFormat::new();
+        let lines = trace
+            .iter()
+            .map(|r| format.display(r).to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            [
+                "CPU2  1000 (+       0)  VMEXIT64  [ 0x00000030 0x00001000 0x00000000 0x00000000 0x00000000 ]",
+                "CPU0  1500 (+       0)  VMENTRY  [ 0x00000000 0x00000000 0x00000000 0x00000000 0x00000000 ]",
+                // Default line of "xentrace_format"
+                "CPU2  2200 (+    1200)  unknown (0x000000000008ffff)  [ 0x00000001 0x00000000 0x00000000 0x00000000 0x00000000 ]",
+            ]
+        );
+    }
+}
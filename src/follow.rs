@@ -0,0 +1,202 @@
+use std::{fs, io, path::Path, thread, time::Duration};
+
+use crate::{
+    reader::{read_event, EventBuffer, ReaderState},
+    record::Record,
+    Error, Result,
+};
+
+/// The default time waited for new data, see [`FollowReader::poll_interval`].
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Streaming reader of the records of a XenTrace binary file being written,
+/// *e.g.* by a running `xentrace`.
+///
+/// Unlike [`RecordReader`](crate::RecordReader), reaching the end of the
+/// data (even in the middle of a record) does not end the reader: records
+/// are yielded as soon as they are completely written.
+///
+/// As an iterator, the reader never ends, waiting for new data when needed
+/// (see [`poll_interval`](FollowReader::poll_interval)), while
+/// [`try_next`](FollowReader::try_next) returns without waiting.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{FollowReader, Result};
+///
+/// fn main() -> Result<()> {
+///     for record in FollowReader::from_file("/path/to/xentrace.bin")? {
+///         println!("{:?}", record?);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FollowReader<R> {
+    rdr: R,
+    events: EventBuffer,
+    state: ReaderState,
+    interval: Duration,
+}
+
+impl FollowReader<fs::File> {
+    /// Constructs a `FollowReader` from a file specified by its path.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to open the trace file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        fs::File::open(path)
+            .map_err(|e| Error::io_error("Failed to open trace file", e))
+            .map(Self::new)
+    }
+}
+
+impl<R: io::Read> FollowReader<R> {
+    /// Constructs a `FollowReader` from any type that implements `io::Read`.
+    ///
+    /// **Note:** The reader is expected to return new data on the following
+    /// reads after reaching its end, as `fs::File` does.
+    pub fn new(reader: R) -> Self {
+        Self {
+            rdr: reader,
+            events: EventBuffer::default(),
+            state: ReaderState::default(),
+            interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Sets the time waited before reading again when no complete
+    /// record is available (100 milliseconds by default).
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Returns the count of CPUs found so far in the trace data.
+    pub fn cpu_count(&self) -> usize {
+        self.state.domains.len()
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    /// Consumes the `FollowReader`, returning the underlying reader.
+    ///
+    /// **Note:** The data of a partially written record is lost.
+    pub fn into_inner(self) -> R {
+        self.rdr
+    }
+
+    /// Returns the next record, if completely written, without waiting for new data.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to read from the underlying reader.
+    pub fn try_next(&mut self) -> Result<Option<Record>> {
+        loop {
+            while let Some(mut data) = self.events.next_event() {
+                let event = read_event(&mut data, &mut self.state.last_tsc)?;
+                if let Some(record) = event.and_then(|e| self.state.next_record(e)) {
+                    return Ok(Some(record));
+                }
+            }
+
+            let result = loop {
+                match self.rdr.read(self.events.spare()) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break result,
+                }
+            };
+
+            match result {
+                Ok(0) => return Ok(None),
+                Ok(len) => self.events.filled(len),
+                Err(e) => return Err(Error::io_error("Failed to read trace data", e)),
+            }
+        }
+    }
+}
+
+impl<R: io::Read> Iterator for FollowReader<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.try_next() {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => thread::sleep(self.interval),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io, rc::Rc};
+
+    use super::FollowReader;
+    use crate::{
+        record::{Domain, DomainKind},
+        RecordReader, TraceBuilder,
+    };
+
+    /// A reader of data that grows over time.
+    struct Growing {
+        data: Rc<RefCell<Vec<u8>>>,
+        pos: usize,
+    }
+
+    impl io::Read for Growing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let data = self.data.borrow();
+            let len = buf.len().min(data.len() - self.pos);
+
+            buf[..len].copy_from_slice(&data[self.pos..self.pos + len]);
+            self.pos += len;
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn follow_test() {
+        let dom1 = Domain::new(DomainKind::Guest(1), 0);
+
+        let bytes = TraceBuilder::new()
+            .schedule(10, dom1)
+            .event(20, 0x00081102, &[1, 2, 3])
+            .cpu(1)
+            .event(30, 0x00081001, &[])
+            .cpu(0)
+            .event(40, 0x00081001, &[])
+            .to_bytes()
+            .unwrap();
+
+        let expected = RecordReader::new(bytes.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let data = Rc::new(RefCell::new(Vec::new()));
+        let mut reader = FollowReader::new(Growing {
+            data: Rc::clone(&data),
+            pos: 0,
+        });
+
+        let mut records = Vec::new();
+        for byte in bytes {
+            data.borrow_mut().push(byte);
+
+            while let Some(record) = reader.try_next().unwrap() {
+                records.push(record);
+            }
+        }
+
+        assert_eq!(records, expected);
+        assert_eq!(records[3].domain(), &dom1);
+    }
+}
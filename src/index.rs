@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use fxhash::FxBuildHasher;
+
+use crate::{
+    record::{DomainKind, EventCode, Record},
+    Trace,
+};
+
+/// Secondary indexes of the [Records](crate::record::Record) of a [`Trace`],
+/// by CPU, by domain and by event code.
+///
+/// Each index maps a key to the (sorted) positions of the matching records
+/// in the trace, so the records are still visited in TSC order.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{record::DomainKind, Result, Trace};
+///
+/// fn main() -> Result<()> {
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///     let index = trace.index();
+///
+///     // VMEXITs of the 5th domain
+///     let exits = index.domain(DomainKind::Guest(5));
+///     for record in index.records(exits).filter(|r| r.event().code() == 0x00081002) {
+///         println!("{:?}", record);
+///     }
+///
+///     println!("Records of CPU 0: {}", index.cpu(0).len());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TraceIndex<'a> {
+    trace: &'a Trace,
+    cpus: HashMap<u32, Vec<usize>, FxBuildHasher>,
+    domains: HashMap<DomainKind, Vec<usize>, FxBuildHasher>,
+    codes: HashMap<EventCode, Vec<usize>, FxBuildHasher>,
+}
+
+impl<'a> TraceIndex<'a> {
+    /// Constructs the indexes of the records of a [`Trace`].
+    pub fn new(trace: &'a Trace) -> Self {
+        let mut index = Self {
+            trace,
+            cpus: HashMap::default(),
+            domains: HashMap::default(),
+            codes: HashMap::default(),
+        };
+
+        for (i, record) in trace.iter().enumerate() {
+            index.cpus.entry(record.cpu).or_default().push(i);
+            index.domains.entry(record.domain.kind).or_default().push(i);
+            index.codes.entry(record.event.code).or_default().push(i);
+        }
+
+        index
+    }
+
+    /// Returns the positions of the records of the given CPU.
+    pub fn cpu(&self, cpu: u32) -> &[usize] {
+        self.cpus.get(&cpu).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the positions of the records of the given domain
+    /// (with all its virtual processors).
+    pub fn domain(&self, kind: DomainKind) -> &[usize] {
+        self.domains.get(&kind).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the positions of the records with the given event code.
+    pub fn code<C: Into<EventCode>>(&self, code: C) -> &[usize] {
+        self.codes.get(&code.into()).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns an iterator over the records at the given positions,
+    /// as returned by the other methods of the index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a position is out of the bounds of the trace.
+    pub fn records<'b>(&self, positions: &'b [usize]) -> impl Iterator<Item = &'a Record> + 'b
+    where
+        'a: 'b,
+    {
+        let trace = self.trace;
+        positions.iter().map(move |i| &trace[*i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        record::{Domain, DomainKind},
+        TraceBuilder,
+    };
+
+    #[test]
+    fn index_test() {
+        let dom1 = Domain::new(DomainKind::Guest(1), 0);
+
+        let trace = TraceBuilder::new()
+            .event(5, 0x00081001, &[])
+            .schedule(10, dom1)
+            .event(20, 0x00081001, &[])
+            .cpu(1)
+            .event(15, 0x00081002, &[1])
+            .build()
+            .unwrap();
+
+        let index = trace.index();
+
+        assert_eq!(index.cpu(0), [0, 1, 3]);
+        assert_eq!(index.cpu(1), [2]);
+        assert!(index.cpu(2).is_empty());
+        assert_eq!(index.domain(DomainKind::Guest(1)), [1, 3]);
+        assert_eq!(index.code(0x00081001), [0, 3]);
+
+        let tscs = index
+            .records(index.domain(DomainKind::Default))
+            .map(|r| r.event().tsc())
+            .collect::<Vec<_>>();
+        assert_eq!(tscs, [5, 15]);
+
+        assert_eq!(trace.find_tsc(0), Some(0));
+        assert_eq!(trace.find_tsc(15), Some(2));
+        assert_eq!(trace.find_tsc(16), Some(3));
+        assert_eq!(trace.find_tsc(21), None);
+    }
+}
@@ -1,11 +1,33 @@
 #![deny(unsafe_code)]
 
+pub mod analysis;
+mod builder;
 pub mod error;
+pub mod export;
+mod follow;
+mod index;
+pub mod query;
+mod reader;
 pub mod record;
+mod seek;
+#[cfg(feature = "async")]
+mod stream;
 mod trace;
+pub mod transform;
+mod trc;
 mod util;
+mod writer;
 
 pub use self::{
+    builder::TraceBuilder,
     error::{Error, Result},
+    follow::FollowReader,
+    index::TraceIndex,
+    reader::RecordReader,
+    seek::SeekIndex,
     trace::Trace,
+    writer::TraceWriter,
 };
+
+#[cfg(feature = "async")]
+pub use self::stream::AsyncRecordReader;
@@ -0,0 +1,540 @@
+//! Filter expressions over the [`Record`]s of a trace.
+//!
+//! A [`Filter`] is parsed from a small expression language and compiled to a
+//! predicate, so it can be applied to the records of a [`Trace`](crate::Trace)
+//! as well as to the ones read by a [`RecordReader`](crate::RecordReader).
+//!
+//! # Syntax
+//!
+//! An expression is made of comparisons between a field of the record and a
+//! value (`==`, `!=`, `<`, `<=`, `>`, `>=`), or of membership tests in a list
+//! of values (`field in (a, b, ...)`), combined with `&&`, `||`, `!` and
+//! parentheses (`&&` binds tighter than `||`).
+//!
+//! | Field                          | Value                                      |
+//! |--------------------------------|--------------------------------------------|
+//! | `cpu`                          | the processor id (of the host)             |
+//! | `tsc`                          | the timestamp of the event                 |
+//! | `dom`, `domain`, `domain.kind` | the domain id, or `dom0`, `domN`, `idle`, `default` |
+//! | `vcpu`, `domain.vcpu`          | the virtual processor number               |
+//! | `code`                         | the complete event code                    |
+//! | `class`, `main`                | the event class, or `gen`, `sched`, `hvm`, `pv`, ... |
+//! | `sub`                          | the event subclass                         |
+//! | `minor`                        | the event minor                            |
+//! | `extra[N]`                     | the `N`-th extra value of the event        |
+//!
+//! Numbers are decimal or hexadecimal (`0x` prefix). Comparisons involving an
+//! extra value missing from the event are always `false`.
+//!
+//! Parentheses and negations can be nested up to 128 levels.
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    record::{Record, EVENT_EXTRA_CAPACITY},
+    trc::class_by_name,
+    Error, Result,
+};
+
+/// The maximum nesting level of the parentheses and negations of an expression.
+const MAX_DEPTH: usize = 128;
+
+/// A compiled filter expression.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{query::Filter, RecordReader, Result, Trace};
+///
+/// fn main() -> Result<()> {
+///     let filter = Filter::parse("dom == 5 && class == hvm && code in (0x81002, 0x81102)")?;
+///
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///     let exits = trace.iter().filter(|r| filter.matches(r)).count();
+///     println!("{} VMEXITs", exits);
+///
+///     // Same, without loading the whole trace
+///     let reader = RecordReader::from_file("/path/to/xentrace.bin")?;
+///     for record in reader.filter(|r| r.as_ref().map_or(true, |r| filter.matches(r))) {
+///         println!("{:?}", record?);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    /// Parses a filter expression.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the expression is not valid.
+    pub fn parse(expr: &str) -> Result<Self> {
+        let tokens = tokenize(expr)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(Self { expr }),
+            Some(token) => Err(parser.unexpected(token)),
+        }
+    }
+
+    /// Returns `true` if the record satisfies the filter.
+    pub fn matches(&self, record: &Record) -> bool {
+        self.expr.eval(record)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Cpu,
+    Tsc,
+    Domain,
+    Vcpu,
+    Code,
+    Main,
+    Sub,
+    Minor,
+    Extra(usize),
+}
+
+impl Field {
+    fn value(&self, record: &Record) -> Option<u64> {
+        let code = &record.event.code;
+
+        match self {
+            Self::Cpu => Some(record.cpu.into()),
+            Self::Tsc => Some(record.event.tsc),
+            Self::Domain => Some(u16::from(record.domain.kind).into()),
+            Self::Vcpu => Some(record.domain.vcpu.into()),
+            Self::Code => Some(code.value().into()),
+            Self::Main => Some(code.main().into()),
+            Self::Sub => Some(code.sub().into()),
+            Self::Minor => Some(code.minor().into()),
+            Self::Extra(index) => record.event.extra[*index].map(u64::from),
+        }
+    }
+
+    /// Resolves a symbolic value (*e.g.* `idle` or `hvm`) of the field.
+    fn resolve(&self, name: &str) -> Option<u64> {
+        match self {
+            Self::Domain => match name {
+                "idle" => Some(32767),
+                "default" => Some(32768),
+                _ => name.strip_prefix("dom")?.parse::<u16>().ok().map(u64::from),
+            },
+            Self::Main => class_by_name(name).map(u64::from),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Cmp(Field, Op, u64),
+    In(Field, Vec<u64>),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, record: &Record) -> bool {
+        match self {
+            Self::Cmp(field, op, rhs) => field.value(record).map_or(false, |lhs| match op {
+                Op::Eq => lhs == *rhs,
+                Op::Ne => lhs != *rhs,
+                Op::Lt => lhs < *rhs,
+                Op::Le => lhs <= *rhs,
+                Op::Gt => lhs > *rhs,
+                Op::Ge => lhs >= *rhs,
+            }),
+            Self::In(field, values) => field
+                .value(record)
+                .map_or(false, |lhs| values.contains(&lhs)),
+            Self::Not(expr) => !expr.eval(record),
+            Self::And(exprs) => exprs.iter().all(|e| e.eval(record)),
+            Self::Or(exprs) => exprs.iter().any(|e| e.eval(record)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(ident) => f.write_str(ident),
+            Self::Number(value) => write!(f, "{}", value),
+            Self::Op(op) => f.write_str(match op {
+                Op::Eq => "==",
+                Op::Ne => "!=",
+                Op::Lt => "<",
+                Op::Le => "<=",
+                Op::Gt => ">",
+                Op::Ge => ">=",
+            }),
+            Self::And => f.write_str("&&"),
+            Self::Or => f.write_str("||"),
+            Self::Not => f.write_str("!"),
+            Self::LParen => f.write_str("("),
+            Self::RParen => f.write_str(")"),
+            Self::LBracket => f.write_str("["),
+            Self::RBracket => f.write_str("]"),
+            Self::Comma => f.write_str(","),
+        }
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let mut next_is = |expected: char| chars.next_if(|(_, c)| *c == expected).is_some();
+
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            ',' => Token::Comma,
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '=' if next_is('=') => Token::Op(Op::Eq),
+            '!' if next_is('=') => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if next_is('=') => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if next_is('=') => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) =
+                    chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+                {
+                    end = i + c.len_utf8();
+                }
+
+                let word = &expr[start..end];
+                if c.is_ascii_digit() {
+                    Token::Number(parse_number(word).ok_or_else(|| {
+                        Error::new(format_args!(
+                            "Failed to parse filter: invalid number '{}'",
+                            word
+                        ))
+                    })?)
+                } else {
+                    Token::Ident(word.to_owned())
+                }
+            }
+            _ => {
+                return Err(Error::new(format_args!(
+                    "Failed to parse filter: unexpected character '{}' at {}",
+                    c, start
+                )))
+            }
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Option<u64> {
+    match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
+
+/// Recursive descent parser of the filter expressions.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token =
+            self.tokens.get(self.pos).cloned().ok_or_else(|| {
+                Error::new("Failed to parse filter: unexpected end of expression")
+            })?;
+
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        let found = self.peek() == Some(expected);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<()> {
+        match self.next()? {
+            token if token == *expected => Ok(()),
+            token => Err(self.unexpected(&token)),
+        }
+    }
+
+    fn unexpected(&self, token: &Token) -> Error {
+        Error::new(format_args!(
+            "Failed to parse filter: unexpected '{}'",
+            token
+        ))
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut exprs = vec![self.parse_and()?];
+        while self.eat(&Token::Or) {
+            exprs.push(self.parse_and()?);
+        }
+
+        Ok(match exprs.len() {
+            1 => exprs.remove(0),
+            _ => Expr::Or(exprs),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut exprs = vec![self.parse_unary()?];
+        while self.eat(&Token::And) {
+            exprs.push(self.parse_unary()?);
+        }
+
+        Ok(match exprs.len() {
+            1 => exprs.remove(0),
+            _ => Expr::And(exprs),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.eat(&Token::Not) {
+            return self.parse_nested(|this| this.parse_unary().map(|e| Expr::Not(Box::new(e))));
+        }
+
+        if self.eat(&Token::LParen) {
+            return self.parse_nested(|this| {
+                let expr = this.parse_or()?;
+                this.expect(&Token::RParen)?;
+                Ok(expr)
+            });
+        }
+
+        self.parse_comparison()
+    }
+
+    /// Parses a nested expression, bounding the recursion depth.
+    fn parse_nested<F>(&mut self, parse: F) -> Result<Expr>
+    where
+        F: FnOnce(&mut Self) -> Result<Expr>,
+    {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::new(format_args!(
+                "Failed to parse filter: more than {} nested levels",
+                MAX_DEPTH
+            )));
+        }
+
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let field = self.parse_field()?;
+
+        match self.next()? {
+            Token::Op(op) => Ok(Expr::Cmp(field, op, self.parse_value(field)?)),
+            Token::Ident(ident) if ident == "in" => {
+                self.expect(&Token::LParen)?;
+
+                let mut values = vec![self.parse_value(field)?];
+                while self.eat(&Token::Comma) {
+                    values.push(self.parse_value(field)?);
+                }
+
+                self.expect(&Token::RParen)?;
+                Ok(Expr::In(field, values))
+            }
+            token => Err(self.unexpected(&token)),
+        }
+    }
+
+    fn parse_field(&mut self) -> Result<Field> {
+        let name = match self.next()? {
+            Token::Ident(name) => name,
+            token => return Err(self.unexpected(&token)),
+        };
+
+        let field = match name.as_str() {
+            "cpu" => Field::Cpu,
+            "tsc" => Field::Tsc,
+            "dom" | "domain" | "domain.kind" => Field::Domain,
+            "vcpu" | "domain.vcpu" => Field::Vcpu,
+            "code" => Field::Code,
+            "class" | "main" => Field::Main,
+            "sub" => Field::Sub,
+            "minor" => Field::Minor,
+            "extra" => {
+                self.expect(&Token::LBracket)?;
+                let index = match self.next()? {
+                    Token::Number(index) if index < EVENT_EXTRA_CAPACITY as u64 => index,
+                    token => return Err(self.unexpected(&token)),
+                };
+                self.expect(&Token::RBracket)?;
+
+                Field::Extra(index as usize)
+            }
+            _ => {
+                return Err(Error::new(format_args!(
+                    "Failed to parse filter: unknown field '{}'",
+                    name
+                )))
+            }
+        };
+
+        Ok(field)
+    }
+
+    fn parse_value(&mut self, field: Field) -> Result<u64> {
+        match self.next()? {
+            Token::Number(value) => Ok(value),
+            Token::Ident(name) => field.resolve(&name).ok_or_else(|| {
+                Error::new(format_args!(
+                    "Failed to parse filter: unknown value '{}'",
+                    name
+                ))
+            }),
+            token => Err(self.unexpected(&token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, MAX_DEPTH};
+    use crate::{record::Domain, TraceBuilder};
+
+    #[test]
+    fn matches_test() {
+        let trace = TraceBuilder::new()
+            .cpu(2)
+            .schedule(1000, Domain::from(0x00050001))
+            .event(2000, 0x00081002, &[48, 0x1000])
+            .cpu(3)
+            .schedule(500, Domain::from(0x7FFF0000))
+            .build()
+            .unwrap();
+        let (idle, exit) = (&trace[0], &trace[2]);
+
+        let filter =
+            Filter::parse("dom == 5 && class == hvm && code in (0x81001, 0x81002) && tsc > 1000")
+                .unwrap();
+        assert!(filter.matches(exit));
+        assert!(!filter.matches(idle));
+
+        let filter: Filter = "domain.kind == idle || extra[0] == 48 && vcpu != 1"
+            .parse()
+            .unwrap();
+        assert!(filter.matches(idle));
+        assert!(!filter.matches(exit));
+
+        let filter = Filter::parse("!(cpu <= 2) && sub == 1 && minor >= 0x101").unwrap();
+        assert!(filter.matches(idle));
+        assert!(!filter.matches(exit));
+
+        // Missing extra values never match
+        let filter = Filter::parse("extra[2] != 0 || !(extra[2] == 0)").unwrap();
+        assert!(filter.matches(exit));
+        assert!(!Filter::parse("extra[2] != 0").unwrap().matches(exit));
+    }
+
+    #[test]
+    fn invalid_test() {
+        for expr in [
+            "",
+            "dom",
+            "dom == ",
+            "dom = 5",
+            "foo == 1",
+            "class == bar",
+            "extra[7] == 0",
+            "code in (1, 2",
+            "cpu == 1 cpu == 2",
+            "(cpu == 1",
+            "tsc > 0xZZ",
+        ] {
+            assert!(Filter::parse(expr).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn depth_test() {
+        let nested = |depth| format!("{}cpu == 0{}", "(!".repeat(depth), ")".repeat(depth));
+
+        assert!(Filter::parse(&nested(MAX_DEPTH / 2)).is_ok());
+        assert!(Filter::parse(&nested(MAX_DEPTH / 2 + 1)).is_err());
+        assert!(Filter::parse(&"(".repeat(100_000)).is_err());
+        assert!(Filter::parse(&"!".repeat(100_000)).is_err());
+
+        // Long chains are not nested
+        let trace = TraceBuilder::new()
+            .event(0, 0x00081001, &[])
+            .build()
+            .unwrap();
+        let chain = vec!["cpu == 0"; 100_000].join(" && ");
+        assert!(Filter::parse(&chain).unwrap().matches(&trace[0]));
+    }
+}
@@ -0,0 +1,264 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use fxhash::FxBuildHasher;
+
+use crate::{
+    record::{Domain, Event, EventCode, Record, EVENT_EXTRA_CAPACITY},
+    trc::{TRC_SCHED_TO_RUN, TRC_TRACE_CPU_CHANGE},
+    util::IoReadUtil,
+    Error, Result,
+};
+
+/// Streaming reader of the records of a XenTrace binary file.
+///
+/// Unlike [`Trace`](crate::Trace), records are yielded in the order
+/// they are stored in the file (sorted by TSC only within each CPU)
+/// and are never kept in memory.
+///
+/// The reader stops at the last readable record, as
+/// [`Trace`](crate::Trace) does.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{RecordReader, Result};
+///
+/// fn main() -> Result<()> {
+///     for record in RecordReader::from_file("/path/to/xentrace.bin")? {
+///         println!("{:?}", record?);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RecordReader<R> {
+    rdr: R,
+    state: ReaderState,
+    start_tsc: u64,
+    done: bool,
+}
+
+/// The state carried between the records of a trace.
+#[derive(Clone, Debug)]
+pub(crate) struct ReaderState {
+    pub(crate) domains: HashMap<u32, Domain, FxBuildHasher>,
+    pub(crate) last_cpu: u32,
+    pub(crate) last_tsc: u64,
+}
+
+impl RecordReader<io::BufReader<fs::File>> {
+    /// Constructs a `RecordReader` from a file specified by its path.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to open the trace file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        fs::File::open(path)
+            .map_err(|e| Error::io_error("Failed to open trace file", e))
+            .map(io::BufReader::new)
+            .map(Self::new)
+    }
+}
+
+impl<R: io::Read> RecordReader<R> {
+    /// Constructs a `RecordReader` from any type that implements `io::Read`.
+    pub fn new(reader: R) -> Self {
+        Self::with_state(reader, ReaderState::default(), 0)
+    }
+
+    /// Constructs a `RecordReader` resuming from the given state,
+    /// skipping the records whose TSC value is lower than `start_tsc`.
+    pub(crate) fn with_state(reader: R, state: ReaderState, start_tsc: u64) -> Self {
+        Self {
+            rdr: reader,
+            state,
+            start_tsc,
+            done: false,
+        }
+    }
+
+    /// Returns the count of CPUs found so far in the trace data.
+    pub fn cpu_count(&self) -> usize {
+        self.state.domains.len()
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    /// Consumes the `RecordReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.rdr
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>> {
+        while let Some(event) = read_event(&mut self.rdr, &mut self.state.last_tsc)? {
+            match self.state.next_record(event) {
+                Some(record) if record.event.tsc >= self.start_tsc => return Ok(Some(record)),
+                _ => (),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<R: io::Read> Iterator for RecordReader<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let record = self.next_record().transpose();
+        self.done = !matches!(record, Some(Ok(_)));
+        record
+    }
+}
+
+impl ReaderState {
+    /// Turns an event into a [`Record`], returning `None` for CPU change events.
+    pub(crate) fn next_record(&mut self, event: Event) -> Option<Record> {
+        if event.code == TRC_TRACE_CPU_CHANGE {
+            self.last_cpu = event.extra[0].unwrap_or(0);
+            return None;
+        }
+
+        let cpu = self.last_cpu;
+        let domain = if event.code == (event.code & TRC_SCHED_TO_RUN) {
+            let extra_0 = event.extra[0].unwrap_or(0);
+            let domain = Domain::from(extra_0);
+            self.domains.insert(cpu, domain);
+            domain
+        } else {
+            self.domains.get(&cpu).copied().unwrap_or_default()
+        };
+
+        Some(Record { cpu, domain, event })
+    }
+}
+
+impl Default for ReaderState {
+    fn default() -> Self {
+        Self {
+            domains: HashMap::with_capacity_and_hasher(
+                u16::BITS as usize,
+                FxBuildHasher::default(),
+            ),
+            last_cpu: 0,
+            last_tsc: 0,
+        }
+    }
+}
+
+pub(crate) fn read_event<R: io::Read>(rdr: &mut R, last_tsc: &mut u64) -> Result<Option<Event>> {
+    // Truncate the reader at the first misread header
+    let Some(header) = rdr.read_ne_u32().ok() else {
+        return Ok(None);
+    };
+
+    let code = EventCode::from(header & 0x0FFFFFF);
+
+    let tsc = {
+        // has "tsc" value ?
+        if header & (1 << 31) > 0 {
+            *last_tsc = rdr
+                .read_ne_u64()
+                .map_err(|e| Error::io_error("Failed to read tsc value", e))?;
+        }
+
+        *last_tsc
+    };
+
+    let extra = {
+        let len = extra_count(header);
+        let mut extra = [None; EVENT_EXTRA_CAPACITY];
+
+        for entry in extra.iter_mut().take(len) {
+            *entry = rdr
+                .read_ne_u32()
+                .map(Some)
+                .map_err(|e| Error::io_error("Failed to read extra value", e))?;
+        }
+
+        extra
+    };
+
+    Ok(Some(Event { code, tsc, extra }))
+}
+
+/// Returns the number of extra values of the event with the given header.
+fn extra_count(header: u32) -> usize {
+    ((header >> 28) as usize) & EVENT_EXTRA_CAPACITY
+}
+
+/// Returns the number of bytes of the event with the given header.
+fn event_len(header: u32) -> usize {
+    let tsc_len = if header & (1 << 31) > 0 { 8 } else { 0 };
+    4 + tsc_len + extra_count(header) * 4
+}
+
+/// Buffer of trace data for the readers that cannot block until
+/// a whole event is read, such as the [`FollowReader`](crate::FollowReader).
+#[derive(Debug)]
+pub(crate) struct EventBuffer {
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+}
+
+impl EventBuffer {
+    /// The capacity of the buffer (far more than the longest event).
+    const CAPACITY: usize = 64 * 1024;
+
+    /// Returns the buffered data not yet consumed.
+    pub(crate) fn pending(&self) -> &[u8] {
+        &self.buf[self.start..self.end]
+    }
+
+    /// Returns the data of the next event, if completely buffered, consuming it.
+    pub(crate) fn next_event(&mut self) -> Option<&[u8]> {
+        let pending = self.pending();
+
+        let header = pending.get(..4)?;
+        let header = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]);
+
+        let len = event_len(header);
+        if pending.len() < len {
+            return None;
+        }
+
+        self.start += len;
+        Some(&self.buf[self.start - len..self.start])
+    }
+
+    /// Returns the free space of the buffer, to be [filled](EventBuffer::filled)
+    /// with new data, dropping the consumed one.
+    pub(crate) fn spare(&mut self) -> &mut [u8] {
+        if self.start > 0 {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+
+        &mut self.buf[self.end..]
+    }
+
+    /// Marks `len` bytes of the free space as filled with new data.
+    pub(crate) fn filled(&mut self, len: usize) {
+        self.end += len;
+    }
+}
+
+impl Default for EventBuffer {
+    fn default() -> Self {
+        Self {
+            buf: vec![0; Self::CAPACITY].into_boxed_slice(),
+            start: 0,
+            end: 0,
+        }
+    }
+}
@@ -1,11 +1,15 @@
+use std::fmt;
+
 /// Type of virtual machine.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DomainKind {
     /// The zero/host domain (*The privileged VM*).
     Zero,
     /// The idle domain (*The CPU is not used*).
     Idle,
     /// The default domain (*No info available*).
+    #[default]
     Default,
     /// The unprivileged domain (*The running VMs*).
     Guest(u16),
@@ -51,9 +55,14 @@ impl PartialEq<DomainKind> for u16 {
     }
 }
 
-impl Default for DomainKind {
-    fn default() -> Self {
-        Self::Default
+impl fmt::Display for DomainKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Zero => f.pad("dom0"),
+            Self::Idle => f.pad("idle"),
+            Self::Default => f.pad("default"),
+            Self::Guest(id) => f.pad(&format!("dom{}", id)),
+        }
     }
 }
 
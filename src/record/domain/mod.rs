@@ -3,7 +3,8 @@ mod kind;
 pub use self::kind::DomainKind;
 
 /// Contains the domain information of the [`Record`](super::Record).
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Domain {
     /// The virtual processor number.
     pub(crate) vcpu: u16,
@@ -12,6 +13,11 @@ pub struct Domain {
 }
 
 impl Domain {
+    /// Constructs a new `Domain` from its [type](DomainKind) and virtual processor number.
+    pub fn new(kind: DomainKind, vcpu: u16) -> Self {
+        Self { vcpu, kind }
+    }
+
     /// Returns the virtual processor number.
     pub fn virtual_cpu(&self) -> u16 {
         self.vcpu
@@ -3,9 +3,11 @@ use std::{
     ops::BitAnd,
 };
 
+use crate::trc::event_name;
+
 /// Contains the event code read as a 32-bit unsigned big-endian integer.
 #[repr(transparent)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct EventCode(u32);
 
 impl EventCode {
@@ -28,6 +30,11 @@ impl EventCode {
     pub fn minor(&self) -> u32 {
         self.0 & 0x00000FFF
     }
+
+    /// Returns the name of the event (*e.g.* `VMEXIT` or `runnable_to_running`), if known.
+    pub fn name(&self) -> Option<&'static str> {
+        event_name(self.0)
+    }
 }
 
 impl From<u32> for EventCode {
@@ -102,6 +109,16 @@ mod tests {
         assert_eq!(ecode1.minor(), ecode2.minor());
     }
 
+    #[test]
+    fn name_test() {
+        assert_eq!(EventCode::from(0x00081002).name(), Some("VMEXIT"));
+        assert_eq!(
+            EventCode::from(0x00021101).name(),
+            Some("runnable_to_running")
+        );
+        assert_eq!(EventCode::from(0x00081FFF).name(), None);
+    }
+
     #[test]
     fn equality_test() {
         let ecode1 = EventCode::from(0x00015003);
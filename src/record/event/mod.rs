@@ -76,7 +76,7 @@ mod tests {
 
     #[test]
     fn sorting_test() {
-        let mut events = vec![
+        let mut events = [
             Event {
                 code: EventCode::from(0x00015004),
                 tsc: 4,
@@ -1,5 +1,7 @@
 mod domain;
 mod event;
+#[cfg(feature = "serde")]
+mod serial;
 
 use std::cmp::Ordering;
 
@@ -8,6 +10,9 @@ pub use self::{
     event::{Event, EventCode, EVENT_EXTRA_CAPACITY},
 };
 
+#[cfg(feature = "serde")]
+pub use self::serial::NamedCodes;
+
 /// Contains information from a single record of the parsed XenTrace binary file.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Record {
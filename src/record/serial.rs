@@ -0,0 +1,317 @@
+use std::fmt;
+
+use serde::{
+    de::{self, Visitor},
+    ser::{SerializeSeq, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{Domain, Event, EventCode, Record, EVENT_EXTRA_CAPACITY};
+use crate::{trc::event_by_name, Trace};
+
+/// Wrapper serializing the event codes of the wrapped value as names
+/// (*e.g.* `"VMEXIT"`), when known, instead of numbers.
+///
+/// Event codes are deserialized from both numbers and names.
+///
+/// *Available with the `serde` feature.*
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{record::NamedCodes, Result, Trace};
+///
+/// fn main() -> Result<()> {
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///
+///     for record in trace.iter() {
+///         // {"cpu":0,"domain":{...},"event":{"code":"VMEXIT","tsc":...,"extra":[...]}}
+///         println!("{}", serde_json::to_string(&NamedCodes(record)).unwrap());
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct NamedCodes<T>(pub T);
+
+/// Serializes an event code, as a name if required and known.
+struct CodeSer(EventCode, bool);
+
+impl Serialize for CodeSer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.name() {
+            Some(name) if self.1 => serializer.serialize_str(name),
+            _ => serializer.serialize_u32(self.0.value()),
+        }
+    }
+}
+
+/// Serializes the extra values of an event as a list, without the missing ones.
+struct ExtraSer<'a>(&'a [Option<u32>; EVENT_EXTRA_CAPACITY]);
+
+impl Serialize for ExtraSer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = self.0.iter().take_while(|e| e.is_some()).count();
+
+        let mut seq = serializer.serialize_seq(Some(len))?;
+        for value in self.0.iter().map_while(|e| *e) {
+            seq.serialize_element(&value)?;
+        }
+        seq.end()
+    }
+}
+
+struct EventSer<'a>(&'a Event, bool);
+
+impl Serialize for EventSer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Event", 3)?;
+        state.serialize_field("code", &CodeSer(self.0.code, self.1))?;
+        state.serialize_field("tsc", &self.0.tsc)?;
+        state.serialize_field("extra", &ExtraSer(&self.0.extra))?;
+        state.end()
+    }
+}
+
+struct RecordSer<'a>(&'a Record, bool);
+
+impl Serialize for RecordSer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Record", 3)?;
+        state.serialize_field("cpu", &self.0.cpu)?;
+        state.serialize_field("domain", &self.0.domain)?;
+        state.serialize_field("event", &EventSer(&self.0.event, self.1))?;
+        state.end()
+    }
+}
+
+struct RecordsSer<'a>(&'a [Record], bool);
+
+impl Serialize for RecordsSer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|r| RecordSer(r, self.1)))
+    }
+}
+
+struct TraceSer<'a>(&'a Trace, bool);
+
+impl Serialize for TraceSer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Trace", 2)?;
+        state.serialize_field("cpu_count", &self.0.cpu_count())?;
+        state.serialize_field("records", &RecordsSer(self.0, self.1))?;
+        state.end()
+    }
+}
+
+impl Serialize for EventCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CodeSer(*self, false).serialize(serializer)
+    }
+}
+
+impl Serialize for Event {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EventSer(self, false).serialize(serializer)
+    }
+}
+
+impl Serialize for Record {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RecordSer(self, false).serialize(serializer)
+    }
+}
+
+impl Serialize for Trace {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TraceSer(self, false).serialize(serializer)
+    }
+}
+
+impl Serialize for NamedCodes<EventCode> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CodeSer(self.0, true).serialize(serializer)
+    }
+}
+
+impl Serialize for NamedCodes<&Event> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EventSer(self.0, true).serialize(serializer)
+    }
+}
+
+impl Serialize for NamedCodes<&Record> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RecordSer(self.0, true).serialize(serializer)
+    }
+}
+
+impl Serialize for NamedCodes<&[Record]> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RecordsSer(self.0, true).serialize(serializer)
+    }
+}
+
+impl Serialize for NamedCodes<&Trace> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TraceSer(self.0, true).serialize(serializer)
+    }
+}
+
+struct CodeVisitor;
+
+impl<'de> Visitor<'de> for CodeVisitor {
+    type Value = EventCode;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an event code or name")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        u32::try_from(value)
+            .map(EventCode::from)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        u32::try_from(value)
+            .map(EventCode::from)
+            .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        event_by_name(value)
+            .map(EventCode::from)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+impl<'de> Deserialize<'de> for EventCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(CodeVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Event")]
+struct EventRepr {
+    code: EventCode,
+    tsc: u64,
+    #[serde(default)]
+    extra: Vec<u32>,
+}
+
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = EventRepr::deserialize(deserializer)?;
+
+        if repr.extra.len() > EVENT_EXTRA_CAPACITY {
+            return Err(de::Error::invalid_length(
+                repr.extra.len(),
+                &"at most 7 extra values",
+            ));
+        }
+
+        let mut extra = [None; EVENT_EXTRA_CAPACITY];
+        for (entry, value) in extra.iter_mut().zip(repr.extra) {
+            *entry = Some(value);
+        }
+
+        Ok(Event {
+            code: repr.code,
+            tsc: repr.tsc,
+            extra,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Record")]
+struct RecordRepr {
+    cpu: u32,
+    domain: Domain,
+    event: Event,
+}
+
+impl<'de> Deserialize<'de> for Record {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = RecordRepr::deserialize(deserializer)?;
+
+        Ok(Record {
+            cpu: repr.cpu,
+            domain: repr.domain,
+            event: repr.event,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Trace")]
+struct TraceRepr {
+    cpu_count: u32,
+    records: Vec<Record>,
+}
+
+impl<'de> Deserialize<'de> for Trace {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = TraceRepr::deserialize(deserializer)?;
+        Ok(Trace::from_records(repr.records, repr.cpu_count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::NamedCodes;
+    use crate::{
+        record::{Domain, DomainKind, Event, Record},
+        Trace, TraceBuilder,
+    };
+
+    #[test]
+    fn record_test() {
+        let trace = TraceBuilder::new()
+            .schedule(10, Domain::new(DomainKind::Guest(1), 2))
+            .event(20, 0x00081102, &[48, 0x1000])
+            .event(30, 0x00081FFF, &[])
+            .build()
+            .unwrap();
+
+        let value = serde_json::to_value(&trace[1]).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "cpu": 0,
+                "domain": { "vcpu": 2, "kind": { "Guest": 1 } },
+                "event": { "code": 0x00081102, "tsc": 20, "extra": [48, 0x1000] },
+            })
+        );
+
+        let named = serde_json::to_value(NamedCodes(&trace[..])).unwrap();
+        assert_eq!(named[1]["event"]["code"], "VMEXIT64");
+        assert_eq!(named[2]["event"]["code"], 0x00081FFF);
+
+        let record = serde_json::from_value::<Record>(named[1].clone()).unwrap();
+        assert_eq!(record, trace[1]);
+
+        let json = serde_json::to_string(&NamedCodes(&trace)).unwrap();
+        let parsed = serde_json::from_str::<Trace>(&json).unwrap();
+        assert_eq!(parsed.cpu_count(), trace.cpu_count());
+        assert_eq!(&parsed[..], &trace[..]);
+    }
+
+    #[test]
+    fn invalid_event_test() {
+        let events = [
+            json!({ "code": "NOT_AN_EVENT", "tsc": 0 }),
+            json!({ "code": 0x1_0000_0000u64, "tsc": 0 }),
+            json!({ "code": 1, "tsc": 0, "extra": [1, 2, 3, 4, 5, 6, 7, 8] }),
+        ];
+
+        for event in events {
+            assert!(serde_json::from_value::<Event>(event).is_err());
+        }
+    }
+}
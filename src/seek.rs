@@ -0,0 +1,485 @@
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    reader::{read_event, ReaderState},
+    record::Domain,
+    trc::TRC_TRACE_CPU_CHANGE,
+    util::{CountingReader, IoReadUtil, IoWriteUtil},
+    Error, RecordReader, Result,
+};
+
+const MAGIC: &[u8; 8] = b"XTINDEX2";
+
+/// The length (in bytes) of the first block of the trace data, whose hash
+/// is stored to detect changes not affecting the length of the trace file.
+const HEAD_LEN: u64 = 4096;
+
+/// The default number of records between two checkpoints of the same segment.
+const DEFAULT_INTERVAL: usize = 1 << 16;
+
+/// A position in the trace data from which reading can be resumed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Checkpoint {
+    /// The byte offset of the next event.
+    offset: u64,
+    /// The CPU of the next event.
+    cpu: u32,
+    /// The TSC value of the previous event.
+    last_tsc: u64,
+    /// The greatest TSC value of the records up to the next checkpoint,
+    /// including the ones of all the previous checkpoints.
+    max_tsc: u64,
+    /// The domain running on each CPU, sorted by CPU.
+    domains: Vec<(u32, Domain)>,
+}
+
+/// Index of the positions of a XenTrace binary file, used to start
+/// reading it from any point in time without parsing what precedes.
+///
+/// A checkpoint is taken at the start of each CPU segment and every
+/// 65536 records within a segment, storing the byte offset of the next
+/// record along with the state needed to resume reading (*e.g.* the
+/// domain running on each CPU).
+///
+/// The index can be saved to a sidecar file next to the trace, see
+/// [`open`](SeekIndex::open). It stores the length and the modification
+/// time of the trace file, along with a hash of its first 4 KiB, to detect
+/// whether the trace has changed since it was indexed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::{fs::File, io::BufReader};
+/// use xentrace_parser::{Result, SeekIndex};
+///
+/// fn main() -> Result<()> {
+///     // Loads "/path/to/xentrace.bin.idx", building it on first use
+///     let index = SeekIndex::open("/path/to/xentrace.bin")?;
+///
+///     let file = File::open("/path/to/xentrace.bin").unwrap();
+///     for record in index.seek(BufReader::new(file), 1_000_000)?.take(10) {
+///         println!("{:?}", record?);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeekIndex {
+    /// The length (in bytes) of the indexed trace data.
+    trace_len: u64,
+    /// The modification time of the indexed trace file
+    /// (in nanoseconds since the Unix epoch), `0` if unknown.
+    trace_mtime: u64,
+    /// The hash of the first block of the indexed trace data.
+    head_hash: u64,
+    /// The checkpoints, sorted by offset.
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl SeekIndex {
+    /// Loads the index of a trace file from its sidecar file (see
+    /// [`sidecar_path`](SeekIndex::sidecar_path)), building and saving
+    /// it if missing or out of date, that is if the length, the
+    /// modification time or the first block of the trace file differ
+    /// from the indexed ones.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to read the trace
+    /// file or to save the sidecar file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let sidecar = Self::sidecar_path(path);
+
+        let mut file =
+            fs::File::open(path).map_err(|e| Error::io_error("Failed to open trace file", e))?;
+        let metadata = file
+            .metadata()
+            .map_err(|e| Error::io_error("Failed to read trace file metadata", e))?;
+
+        let trace_len = metadata.len();
+        let trace_mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |time| time.as_nanos() as u64);
+        let head_hash = read_head(&mut file).map(|head| fxhash::hash64(&head))?;
+
+        let saved = fs::File::open(&sidecar)
+            .ok()
+            .and_then(|file| Self::load(io::BufReader::new(file)).ok());
+
+        match saved {
+            Some(index)
+                if index.trace_len == trace_len
+                    && index.trace_mtime == trace_mtime
+                    && index.head_hash == head_hash =>
+            {
+                Ok(index)
+            }
+            _ => {
+                file.seek(SeekFrom::Start(0))
+                    .map_err(|e| Error::io_error("Failed to seek trace file", e))?;
+
+                let mut index = Self::build(io::BufReader::new(file))?;
+                index.trace_mtime = trace_mtime;
+
+                fs::File::create(&sidecar)
+                    .map_err(|e| Error::io_error("Failed to create index file", e))
+                    .map(io::BufWriter::new)
+                    .and_then(|file| index.save(file))?;
+
+                Ok(index)
+            }
+        }
+    }
+
+    /// Returns the path of the sidecar file of a trace file,
+    /// which is the trace path with an additional `.idx` extension.
+    pub fn sidecar_path<P: AsRef<Path>>(path: P) -> PathBuf {
+        let mut path = path.as_ref().as_os_str().to_owned();
+        path.push(".idx");
+        path.into()
+    }
+
+    /// Builds the index of the trace data read from any type that implements `io::Read`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to parse the trace data.
+    pub fn build<R: io::Read>(reader: R) -> Result<Self> {
+        Self::build_with_interval(reader, DEFAULT_INTERVAL)
+    }
+
+    fn build_with_interval<R: io::Read>(mut reader: R, interval: usize) -> Result<Self> {
+        let head = read_head(&mut reader)?;
+        let head_hash = fxhash::hash64(&head);
+
+        let mut rdr = CountingReader::new(head.as_slice().chain(reader));
+        let mut state = ReaderState::default();
+        let mut checkpoints = vec![Checkpoint::new(0, &state)];
+
+        // A running maximum, as the segments of a CPU may end
+        // later than the following segments of the other CPUs
+        let mut max_tsc = 0;
+        let mut since_checkpoint = 0;
+
+        while let Some(event) = read_event(&mut rdr, &mut state.last_tsc)? {
+            let cpu_change = event.code == TRC_TRACE_CPU_CHANGE;
+
+            if let Some(record) = state.next_record(event) {
+                max_tsc = max_tsc.max(record.event.tsc);
+                since_checkpoint += 1;
+            }
+
+            if cpu_change || since_checkpoint >= interval {
+                if let Some(last) = checkpoints.last_mut() {
+                    last.max_tsc = max_tsc;
+                }
+
+                checkpoints.push(Checkpoint::new(rdr.count(), &state));
+                since_checkpoint = 0;
+            }
+        }
+
+        if let Some(last) = checkpoints.last_mut() {
+            last.max_tsc = max_tsc;
+        }
+
+        Ok(Self {
+            trace_len: rdr.count(),
+            trace_mtime: 0,
+            head_hash,
+            checkpoints,
+        })
+    }
+
+    /// Loads an index previously [saved](SeekIndex::save).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to read the index
+    /// or if the data is not a valid index.
+    pub fn load<R: io::Read>(mut reader: R) -> Result<Self> {
+        let map_err = |e| Error::io_error("Failed to read index", e);
+
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic).map_err(map_err)?;
+        if &magic != MAGIC {
+            return Err(Error::new("Failed to read index: invalid magic number"));
+        }
+
+        let trace_len = reader.read_ne_u64().map_err(map_err)?;
+        let trace_mtime = reader.read_ne_u64().map_err(map_err)?;
+        let head_hash = reader.read_ne_u64().map_err(map_err)?;
+        let count = reader.read_ne_u64().map_err(map_err)?;
+        let mut running_max_tsc = 0;
+
+        let mut checkpoints = Vec::new();
+        for _ in 0..count {
+            let offset = reader.read_ne_u64().map_err(map_err)?;
+            let cpu = reader.read_ne_u32().map_err(map_err)?;
+            let last_tsc = reader.read_ne_u64().map_err(map_err)?;
+            let max_tsc = reader.read_ne_u64().map_err(map_err)?;
+            running_max_tsc = max_tsc.max(running_max_tsc);
+
+            let domain_count = reader.read_ne_u32().map_err(map_err)?;
+            let domains = (0..domain_count)
+                .map(|_| {
+                    let cpu = reader.read_ne_u32()?;
+                    let domain = reader.read_ne_u32()?;
+                    Ok((cpu, Domain::from(domain)))
+                })
+                .collect::<io::Result<Vec<_>>>()
+                .map_err(map_err)?;
+
+            checkpoints.push(Checkpoint {
+                offset,
+                cpu,
+                last_tsc,
+                max_tsc: running_max_tsc,
+                domains,
+            });
+        }
+
+        Ok(Self {
+            trace_len,
+            trace_mtime,
+            head_hash,
+            checkpoints,
+        })
+    }
+
+    /// Saves the index to any type that implements `io::Write`,
+    /// in a compact binary format (in host endian, as the trace data).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write the index.
+    pub fn save<W: io::Write>(&self, mut writer: W) -> Result<()> {
+        let mut write = || {
+            writer.write_all(MAGIC)?;
+            writer.write_ne_u64(self.trace_len)?;
+            writer.write_ne_u64(self.trace_mtime)?;
+            writer.write_ne_u64(self.head_hash)?;
+            writer.write_ne_u64(self.checkpoints.len() as u64)?;
+
+            for checkpoint in &self.checkpoints {
+                writer.write_ne_u64(checkpoint.offset)?;
+                writer.write_ne_u32(checkpoint.cpu)?;
+                writer.write_ne_u64(checkpoint.last_tsc)?;
+                writer.write_ne_u64(checkpoint.max_tsc)?;
+
+                writer.write_ne_u32(checkpoint.domains.len() as u32)?;
+                for (cpu, domain) in &checkpoint.domains {
+                    writer.write_ne_u32(*cpu)?;
+                    writer.write_ne_u32(domain.into())?;
+                }
+            }
+
+            writer.flush()
+        };
+
+        write().map_err(|e| Error::io_error("Failed to write index", e))
+    }
+
+    /// Returns the length (in bytes) of the indexed trace data.
+    pub fn trace_len(&self) -> u64 {
+        self.trace_len
+    }
+
+    /// Returns a [`RecordReader`] over the records whose TSC value is greater
+    /// than or equal to `tsc`, positioning the reader (over the indexed trace
+    /// data) at the first checkpoint that may precede one of them.
+    ///
+    /// The checkpoint is found through a binary search, then at most the
+    /// records between two checkpoints are skipped. As with the
+    /// [`RecordReader`], records are yielded in the order they are stored.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to seek the reader.
+    pub fn seek<R: io::Read + io::Seek>(&self, mut reader: R, tsc: u64) -> Result<RecordReader<R>> {
+        // The records preceding the found checkpoint are all earlier than "tsc"
+        let i = self.checkpoints.partition_point(|c| c.max_tsc < tsc);
+
+        let (offset, state) = match self.checkpoints.get(i) {
+            Some(checkpoint) => (checkpoint.offset, checkpoint.state()),
+            None => (self.trace_len, ReaderState::default()),
+        };
+
+        reader
+            .seek(SeekFrom::Start(offset))
+            .map_err(|e| Error::io_error("Failed to seek trace data", e))?;
+
+        Ok(RecordReader::with_state(reader, state, tsc))
+    }
+}
+
+/// Reads the first block of the trace data (or less, if shorter).
+fn read_head<R: io::Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(HEAD_LEN as usize);
+    reader
+        .take(HEAD_LEN)
+        .read_to_end(&mut head)
+        .map_err(|e| Error::io_error("Failed to read trace data", e))?;
+
+    Ok(head)
+}
+
+impl Checkpoint {
+    fn new(offset: u64, state: &ReaderState) -> Self {
+        let mut domains = state
+            .domains
+            .iter()
+            .map(|(cpu, domain)| (*cpu, *domain))
+            .collect::<Vec<_>>();
+        domains.sort_unstable_by_key(|(cpu, _)| *cpu);
+
+        Self {
+            offset,
+            cpu: state.last_cpu,
+            last_tsc: state.last_tsc,
+            max_tsc: 0,
+            domains,
+        }
+    }
+
+    fn state(&self) -> ReaderState {
+        let mut state = ReaderState::default();
+        state.domains.extend(self.domains.iter().copied());
+        state.last_cpu = self.cpu;
+        state.last_tsc = self.last_tsc;
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use super::SeekIndex;
+    use crate::{
+        record::{Domain, DomainKind},
+        RecordReader, TraceBuilder,
+    };
+
+    #[test]
+    fn seek_test() {
+        let dom1 = Domain::new(DomainKind::Guest(1), 0);
+        let dom2 = Domain::new(DomainKind::Guest(2), 0);
+
+        // Interleaved segments, then a long segment of a single CPU
+        let mut builder = TraceBuilder::new()
+            .schedule(0, dom1)
+            .cpu(1)
+            .schedule(0, dom2);
+        for tsc in 1..50 {
+            builder = builder.cpu(0).event(tsc * 10, 0x00081001, &[]);
+            builder = builder.cpu(1).event(tsc * 10 + 5, 0x00081001, &[]);
+        }
+        for tsc in 50..100 {
+            builder = builder.event(tsc * 10, 0x00081001, &[]);
+        }
+        let bytes = builder.to_bytes().unwrap();
+
+        let index = SeekIndex::build_with_interval(bytes.as_slice(), 8).unwrap();
+        assert_eq!(index.trace_len(), bytes.len() as u64);
+
+        let mut saved = Vec::new();
+        index.save(&mut saved).unwrap();
+        assert_eq!(SeekIndex::load(saved.as_slice()).unwrap(), index);
+
+        for tsc in [0, 7, 300, 500, 735, 990, 991, 2000] {
+            let expected = RecordReader::new(bytes.as_slice())
+                .map(Result::unwrap)
+                .filter(|r| r.event().tsc() >= tsc)
+                .collect::<Vec<_>>();
+
+            let seeked = index
+                .seek(Cursor::new(&bytes), tsc)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect::<Vec<_>>();
+
+            assert_eq!(seeked, expected, "tsc {}", tsc);
+        }
+    }
+
+    #[test]
+    fn long_segment_test() {
+        // A long segment of a quiet CPU, then short segments of a busy one
+        let mut builder =
+            TraceBuilder::new()
+                .event(0, 0x00081001, &[])
+                .event(1000, 0x00081001, &[]);
+        for tsc in 1..10 {
+            builder = builder.cpu(1).event(tsc * 100, 0x00081001, &[]);
+            builder = builder.cpu(2).event(tsc * 100 + 50, 0x00081001, &[]);
+        }
+        let bytes = builder.to_bytes().unwrap();
+
+        let index = SeekIndex::build_with_interval(bytes.as_slice(), 1).unwrap();
+
+        let mut saved = Vec::new();
+        index.save(&mut saved).unwrap();
+        assert_eq!(SeekIndex::load(saved.as_slice()).unwrap(), index);
+
+        for tsc in [0, 150, 500, 950, 1000, 1001] {
+            let expected = RecordReader::new(bytes.as_slice())
+                .map(Result::unwrap)
+                .filter(|r| r.event().tsc() >= tsc)
+                .collect::<Vec<_>>();
+
+            let seeked = index
+                .seek(Cursor::new(&bytes), tsc)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect::<Vec<_>>();
+
+            assert_eq!(seeked, expected, "tsc {}", tsc);
+        }
+    }
+
+    #[test]
+    fn sidecar_test() {
+        let trace = |tsc| {
+            TraceBuilder::new()
+                .schedule(tsc, Domain::new(DomainKind::Guest(1), 0))
+                .event(tsc + 10, 0x00081001, &[])
+                .to_bytes()
+                .unwrap()
+        };
+
+        let path = std::env::temp_dir().join(format!("xentrace-seek-{}.bin", std::process::id()));
+        let sidecar = SeekIndex::sidecar_path(&path);
+
+        fs::write(&path, trace(100)).unwrap();
+        let index = SeekIndex::open(&path).unwrap();
+        assert!(sidecar.exists());
+        assert_eq!(SeekIndex::open(&path).unwrap(), index);
+
+        // Same length, different contents
+        fs::write(&path, trace(200)).unwrap();
+        let reopened = SeekIndex::open(&path).unwrap();
+
+        // A stale index would end before this TSC value
+        let seeked = reopened
+            .seek(fs::File::open(&path).unwrap(), 150)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(reopened.trace_len(), index.trace_len());
+        assert_eq!(seeked.len(), 2);
+        assert_eq!(seeked[0].event().tsc(), 200);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&sidecar).unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    future, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use futures_io::AsyncRead;
+
+use crate::{
+    reader::{read_event, EventBuffer, ReaderState},
+    record::Record,
+    Error, Result,
+};
+
+/// Asynchronous streaming reader of the records of a XenTrace binary file,
+/// over any type that implements `futures::io::AsyncRead`.
+///
+/// This is the asynchronous version of [`RecordReader`](crate::RecordReader):
+/// records are yielded in the order they are stored in the file and the
+/// stream stops at the last readable record. It does not depend on any
+/// runtime (the `tokio` readers can be used through `tokio-util`'s `compat`).
+///
+/// *Available with the `async` feature.*
+///
+/// # Examples
+///
+/// ```no_run
+/// use futures_io::AsyncRead;
+/// use xentrace_parser::{AsyncRecordReader, Result};
+///
+/// async fn count_records<R: AsyncRead + Unpin>(reader: R) -> Result<usize> {
+///     let mut reader = AsyncRecordReader::new(reader);
+///     let mut count = 0;
+///
+///     while let Some(record) = reader.next_record().await {
+///         record?;
+///         count += 1;
+///     }
+///
+///     Ok(count)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AsyncRecordReader<R> {
+    rdr: R,
+    events: EventBuffer,
+    state: ReaderState,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRecordReader<R> {
+    /// Constructs an `AsyncRecordReader` from any type that implements `AsyncRead`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            rdr: reader,
+            events: EventBuffer::default(),
+            state: ReaderState::default(),
+            done: false,
+        }
+    }
+
+    /// Returns the count of CPUs found so far in the trace data.
+    pub fn cpu_count(&self) -> usize {
+        self.state.domains.len()
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    /// Consumes the `AsyncRecordReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.rdr
+    }
+
+    /// Returns the next record, or `None` at the end of the trace data.
+    pub async fn next_record(&mut self) -> Option<Result<Record>> {
+        future::poll_fn(|cx| self.poll_record(cx)).await
+    }
+
+    fn poll_record(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Record>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        let record = match self.poll_next_record(cx) {
+            Poll::Ready(record) => record.transpose(),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        self.done = !matches!(record, Some(Ok(_)));
+        Poll::Ready(record)
+    }
+
+    fn poll_next_record(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Record>>> {
+        loop {
+            while let Some(mut data) = self.events.next_event() {
+                let event = read_event(&mut data, &mut self.state.last_tsc)?;
+                if let Some(record) = event.and_then(|e| self.state.next_record(e)) {
+                    return Poll::Ready(Ok(Some(record)));
+                }
+            }
+
+            match Pin::new(&mut self.rdr).poll_read(cx, self.events.spare()) {
+                Poll::Ready(Ok(0)) => {
+                    // Parse the partial event, if any, as the synchronous reader does
+                    let mut data = self.events.pending();
+                    let event = read_event(&mut data, &mut self.state.last_tsc)?;
+                    return Poll::Ready(Ok(event.and_then(|e| self.state.next_record(e))));
+                }
+                Poll::Ready(Ok(len)) => self.events.filled(len),
+                Poll::Ready(Err(e)) if e.kind() == io::ErrorKind::Interrupted => (),
+                Poll::Ready(Err(e)) => {
+                    return Poll::Ready(Err(Error::io_error("Failed to read trace data", e)))
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for AsyncRecordReader<R> {
+    type Item = Result<Record>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_record(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_executor::block_on;
+
+    use super::AsyncRecordReader;
+    use crate::{
+        record::{Domain, DomainKind},
+        RecordReader, TraceBuilder,
+    };
+
+    #[test]
+    fn stream_test() {
+        let bytes = TraceBuilder::new()
+            .schedule(10, Domain::new(DomainKind::Guest(1), 0))
+            .event(20, 0x00081102, &[1, 2, 3])
+            .cpu(1)
+            .event(30, 0x00081001, &[])
+            .to_bytes()
+            .unwrap();
+
+        let expected = RecordReader::new(bytes.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let mut reader = AsyncRecordReader::new(bytes.as_slice());
+        let mut records = Vec::new();
+        while let Some(record) = block_on(reader.next_record()) {
+            records.push(record.unwrap());
+        }
+
+        assert_eq!(records, expected);
+
+        // Truncated in the middle of an event
+        let truncated = &bytes[..bytes.len() - 2];
+        let mut reader = AsyncRecordReader::new(truncated);
+        let results = block_on(async {
+            let mut results = Vec::new();
+            while let Some(record) = reader.next_record().await {
+                results.push(record);
+            }
+            results
+        });
+
+        assert_eq!(results.len(), expected.len());
+        assert!(results.last().unwrap().is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use std::{fs, io, ops::Deref, path::Path};
 
 use self::parse::parse_trace;
-use crate::{record::Record, Error, Result};
+use crate::{record::Record, transform::MergeReport, Error, Result, TraceIndex};
 
 /// Represents a parsed XenTrace binary file.
 ///
@@ -10,7 +10,7 @@ use crate::{record::Record, Error, Result};
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// use xentrace_parser::{Result, Trace};
 ///
 /// fn function() -> Result<()> {
@@ -35,6 +35,16 @@ pub struct Trace {
 }
 
 impl Trace {
+    /// Constructs a `Trace` from its records, sorting them by TSC.
+    pub(crate) fn from_records(mut records: Vec<Record>, cpu_count: u32) -> Self {
+        records.sort();
+
+        Self {
+            records: records.into_boxed_slice(),
+            cpu_count,
+        }
+    }
+
     /// Constructs a `Trace` from a file specified by its path.
     ///
     /// # Errors
@@ -44,7 +54,7 @@ impl Trace {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
     /// use xentrace_parser::{Result, Trace};
     ///
     /// fn main() -> Result<()> {
@@ -91,12 +101,13 @@ impl Trace {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
+    /// use std::error::Error;
     /// use std::fs::File;
     /// use std::io::BufReader;
-    /// use xentrace_parser::{Trace, Result};
+    /// use xentrace_parser::Trace;
     ///
-    /// fn main() -> Result<()> {
+    /// fn main() -> Result<(), Box<dyn Error>> {
     ///     let file = File::open("/path/to/xentrace.bin")?;
     ///     let bufreader = BufReader::new(file);
     ///     let trace = Trace::from_reader(bufreader)?;
@@ -112,7 +123,7 @@ impl Trace {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
     /// use xentrace_parser::{Trace, Result};
     ///
     /// fn main() -> Result<()> {
@@ -133,7 +144,7 @@ impl Trace {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
     /// use xentrace_parser::{Trace, Result};
     ///
     /// fn main() -> Result<()> {
@@ -146,6 +157,112 @@ impl Trace {
     pub fn cpu_count(&self) -> u32 {
         self.cpu_count
     }
+
+    /// Returns an iterator over the [Records](crate::record::Record) whose TSC
+    /// value is in the range from `start` (inclusive) to `end` (exclusive), as
+    /// [`transform::slice_time`](crate::transform::slice_time) selects them.
+    ///
+    /// If `cpus` is given, only the records of those CPUs are returned.
+    ///
+    /// The range is found through a binary search, since the records are sorted by TSC.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use xentrace_parser::{Trace, Result};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+    ///     let window = trace.slice_time(1_000_000, 2_000_000, Some(&[0, 1]));
+    ///     println!("Records in window: {}", window.count());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn slice_time<'a>(
+        &'a self,
+        start: u64,
+        end: u64,
+        cpus: Option<&'a [u32]>,
+    ) -> impl Iterator<Item = &'a Record> + 'a {
+        let len = self.records.len();
+        let from = self.find_tsc(start).unwrap_or(len);
+        let to = self.find_tsc(end).unwrap_or(len).max(from);
+
+        self.records[from..to]
+            .iter()
+            .filter(move |r| cpus.map_or(true, |cpus| cpus.contains(&r.cpu)))
+    }
+
+    /// Returns the position of the first [Record](crate::record::Record)
+    /// whose TSC value is greater than or equal to `tsc`, if any.
+    ///
+    /// The record is found through a binary search, since they are sorted by TSC.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use xentrace_parser::{Trace, Result};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+    ///     if let Some(i) = trace.find_tsc(1_000_000) {
+    ///         println!("First record from TSC 1000000: {:?}", trace[i]);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn find_tsc(&self, tsc: u64) -> Option<usize> {
+        let i = self.records.partition_point(|r| r.event.tsc < tsc);
+        (i < self.records.len()).then_some(i)
+    }
+
+    /// Builds the secondary indexes (by CPU, by domain and by event code)
+    /// of the [Records](crate::record::Record), see [`TraceIndex`].
+    ///
+    /// **Note:** The indexes are not cached, so the returned value
+    /// should be kept as long as it is needed.
+    pub fn index(&self) -> TraceIndex<'_> {
+        TraceIndex::new(self)
+    }
+
+    /// Merges multiple traces (*e.g.* captures split across several files)
+    /// into a single one, sorted by TSC, returning it along with a
+    /// [`MergeReport`] of the overlaps and gaps between the traces.
+    ///
+    /// Records with the same TSC value keep the order of the merged traces.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use xentrace_parser::{Trace, Result};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let first = Trace::from_file("/path/to/xentrace-1.bin")?;
+    ///     let second = Trace::from_file("/path/to/xentrace-2.bin")?;
+    ///
+    ///     let (trace, report) = Trace::merge([first, second]);
+    ///     println!("Record count: {}", trace.record_count());
+    ///     println!("Overlaps: {:?}", report.overlaps());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn merge<I: IntoIterator<Item = Trace>>(traces: I) -> (Self, MergeReport) {
+        let mut records = Vec::new();
+        let mut spans = Vec::new();
+        let mut cpu_count = 0;
+
+        for trace in traces {
+            let first = trace.records.first().map(|r| r.event.tsc);
+            let last = trace.records.last().map(|r| r.event.tsc);
+
+            spans.push(first.zip(last));
+            cpu_count = cpu_count.max(trace.cpu_count);
+            records.extend(Vec::from(trace.records));
+        }
+
+        let trace = Trace::from_records(records, cpu_count);
+        (trace, MergeReport::from_spans(spans))
+    }
 }
 
 impl Deref for Trace {
@@ -158,112 +275,23 @@ impl Deref for Trace {
 
 // Functions for trace parsing logic
 mod parse {
-    use std::collections::HashMap;
-
-    use fxhash::FxBuildHasher;
-
     use super::*;
-    use crate::{
-        record::{Domain, Event, EventCode, EVENT_EXTRA_CAPACITY},
-        util::IoReadUtil,
-    };
-
-    const TRC_TRACE_CPU_CHANGE: u32 = 0x0001F003;
-    const TRC_SCHED_TO_RUN: u32 = 0x00021F0F;
-
-    struct ParserData {
-        domains: HashMap<u32, Domain, FxBuildHasher>,
-        last_cpu: u32,
-        records: Vec<Record>,
-        last_tsc: u64,
-    }
-
-    pub(super) fn parse_trace<R: io::Read>(mut rdr: R) -> Result<Trace> {
-        let mut data = ParserData {
-            domains: HashMap::with_capacity_and_hasher(
-                u16::BITS as usize,
-                FxBuildHasher::default(),
-            ),
-            last_cpu: 0,
-            records: Vec::with_capacity((u16::MAX / 2) as usize),
-            last_tsc: 0,
-        };
+    use crate::reader::RecordReader;
 
-        while let Some(record) = next_record(&mut rdr, &mut data)? {
-            if record.event.code == TRC_TRACE_CPU_CHANGE {
-                data.last_cpu = record.event.extra[0].unwrap_or(0);
-                continue;
-            }
+    pub(super) fn parse_trace<R: io::Read>(rdr: R) -> Result<Trace> {
+        let mut reader = RecordReader::new(rdr);
+        let mut records = Vec::with_capacity((u16::MAX / 2) as usize);
 
-            data.records.push(record);
+        for record in &mut reader {
+            records.push(record?);
         }
 
-        let records = {
-            data.records.sort();
-            data.records.into_boxed_slice()
-        };
-
-        match data.domains.len().try_into() {
-            Ok(cpu_count) => Ok(Trace { records, cpu_count }),
+        match reader.cpu_count().try_into() {
+            Ok(cpu_count) => Ok(Trace::from_records(records, cpu_count)),
             Err(_) => Err(Error::new(format_args!(
                 "Failed to set host CPU count: {} > u32::MAX",
-                data.domains.len()
+                reader.cpu_count()
             ))),
         }
     }
-
-    fn next_record<R: io::Read>(rdr: &mut R, data: &mut ParserData) -> Result<Option<Record>> {
-        fn read_event<R: io::Read>(rdr: &mut R, last_tsc: &mut u64) -> Result<Option<Event>> {
-            // Truncate the reader at the first misread header
-            let Some(header) = rdr.read_ne_u32().ok() else {
-                return Ok(None);
-            };
-
-            let code = EventCode::from(header & 0x0FFFFFF);
-
-            let tsc = {
-                // has "tsc" value ?
-                if header & (1 << 31) > 0 {
-                    *last_tsc = rdr
-                        .read_ne_u64()
-                        .map_err(|e| Error::io_error("Failed to read tsc value", e))?;
-                }
-
-                *last_tsc
-            };
-
-            let extra = {
-                let len = ((header >> 28) as usize) & EVENT_EXTRA_CAPACITY;
-                let mut extra = [None; EVENT_EXTRA_CAPACITY];
-
-                for entry in extra.iter_mut().take(len) {
-                    *entry = rdr
-                        .read_ne_u32()
-                        .map(Some)
-                        .map_err(|e| Error::io_error("Failed to read extra value", e))?;
-                }
-
-                extra
-            };
-
-            Ok(Some(Event { code, tsc, extra }))
-        }
-
-        // "next_record" function
-        let Some(event) = read_event(rdr, &mut data.last_tsc)? else {
-            return Ok(None);
-        };
-
-        let cpu = data.last_cpu;
-        let domain = if event.code == (event.code & TRC_SCHED_TO_RUN) {
-            let extra_0 = event.extra[0].unwrap_or(0);
-            let domain = Domain::from(extra_0);
-            data.domains.insert(cpu, domain);
-            domain
-        } else {
-            data.domains.get(&cpu).copied().unwrap_or_default()
-        };
-
-        Ok(Some(Record { cpu, domain, event }))
-    }
 }
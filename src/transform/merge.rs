@@ -0,0 +1,442 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+};
+
+use crate::{record::Record, RecordReader, Result, TraceWriter};
+
+/// The time range in which two merged traces overlap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Overlap {
+    /// The index of the first trace.
+    pub(crate) first: usize,
+    /// The index of the second trace.
+    pub(crate) second: usize,
+    /// The timestamp at which the overlap starts.
+    pub(crate) start_tsc: u64,
+    /// The timestamp at which the overlap ends.
+    pub(crate) end_tsc: u64,
+}
+
+impl Overlap {
+    /// Returns the indices of the overlapping traces, in the order they have been merged.
+    pub fn traces(&self) -> (usize, usize) {
+        (self.first, self.second)
+    }
+
+    /// Returns the timestamp at which the overlap starts.
+    pub fn start_tsc(&self) -> u64 {
+        self.start_tsc
+    }
+
+    /// Returns the timestamp at which the overlap ends.
+    pub fn end_tsc(&self) -> u64 {
+        self.end_tsc
+    }
+}
+
+/// A time range not covered by any of the merged traces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Gap {
+    /// The timestamp of the last record before the gap.
+    pub(crate) start_tsc: u64,
+    /// The timestamp of the first record after the gap.
+    pub(crate) end_tsc: u64,
+}
+
+impl Gap {
+    /// Returns the timestamp of the last record before the gap.
+    pub fn start_tsc(&self) -> u64 {
+        self.start_tsc
+    }
+
+    /// Returns the timestamp of the first record after the gap.
+    pub fn end_tsc(&self) -> u64 {
+        self.end_tsc
+    }
+
+    /// Returns the duration of the gap (in CPU cycles).
+    pub fn duration(&self) -> u64 {
+        self.end_tsc - self.start_tsc
+    }
+}
+
+/// Report of the time ranges covered by merged traces.
+///
+/// Only the records of the merged traces are taken into account, not the
+/// [synthetic records](crate::transform#synthetic-records) written by [`merge`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    spans: Vec<Option<(u64, u64)>>,
+    overlaps: Vec<Overlap>,
+    gaps: Vec<Gap>,
+}
+
+impl MergeReport {
+    /// Constructs a `MergeReport` from the `(first, last)` TSC values of each trace.
+    pub(crate) fn from_spans(spans: Vec<Option<(u64, u64)>>) -> Self {
+        let mut overlaps = Vec::new();
+
+        for (first, a) in spans.iter().enumerate() {
+            let Some((a_start, a_end)) = a else {
+                continue;
+            };
+
+            for (second, b) in spans.iter().enumerate().skip(first + 1) {
+                let Some((b_start, b_end)) = b else {
+                    continue;
+                };
+
+                let start_tsc = *a_start.max(b_start);
+                let end_tsc = *a_end.min(b_end);
+
+                if start_tsc <= end_tsc {
+                    overlaps.push(Overlap {
+                        first,
+                        second,
+                        start_tsc,
+                        end_tsc,
+                    });
+                }
+            }
+        }
+
+        let mut covered = spans.iter().flatten().copied().collect::<Vec<_>>();
+        covered.sort_unstable();
+
+        let mut gaps = Vec::new();
+        let mut covered_end = None;
+
+        for (start, end) in covered {
+            match covered_end {
+                Some(covered_end) if start > covered_end => gaps.push(Gap {
+                    start_tsc: covered_end,
+                    end_tsc: start,
+                }),
+                _ => (),
+            }
+
+            covered_end = Some(covered_end.map_or(end, |e: u64| e.max(end)));
+        }
+
+        Self {
+            spans,
+            overlaps,
+            gaps,
+        }
+    }
+
+    /// Returns the `(first, last)` TSC values of each merged trace
+    /// (`None` for the traces without records), in the order they have been merged.
+    pub fn spans(&self) -> &[Option<(u64, u64)>] {
+        &self.spans
+    }
+
+    /// Returns the time ranges in which two traces overlap.
+    pub fn overlaps(&self) -> &[Overlap] {
+        &self.overlaps
+    }
+
+    /// Returns the time ranges not covered by any trace, sorted by time.
+    pub fn gaps(&self) -> &[Gap] {
+        &self.gaps
+    }
+
+    /// Sets the minimum duration (in CPU cycles) of the reported gaps,
+    /// leaving out the shorter ones (`0` by default).
+    pub fn min_gap(mut self, cycles: u64) -> Self {
+        self.gaps.retain(|gap| gap.duration() >= cycles);
+        self
+    }
+}
+
+/// Merges the records read by `readers` into a single trace written to `writer`,
+/// as XenTrace binary data, returning the underlying writer and a [`MergeReport`].
+///
+/// Records are merged by TSC separately for each CPU, as a reader yields them
+/// sorted only within each CPU: a record is written once every other reader has
+/// a next record on the same CPU (or has none left), the earliest one being taken
+/// at each step (ties are broken in favor of the first reader). The records of
+/// the other CPUs read meanwhile are buffered, so merging traces that do not share
+/// the same CPUs may read a whole trace ahead.
+///
+/// The records of a CPU are written for as long as the readers are on it, so
+/// that the written segments are about as long as the ones read.
+/// The domain of each record is preserved, adding
+/// [synthetic records](crate::transform#synthetic-records).
+///
+/// # Errors
+///
+/// This function will return an error if it fails to read or write a record.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::{fs::File, io::BufWriter};
+/// use xentrace_parser::{transform, RecordReader, Result};
+///
+/// fn main() -> Result<()> {
+///     let readers = vec![
+///         RecordReader::from_file("/path/to/xentrace-1.bin")?,
+///         RecordReader::from_file("/path/to/xentrace-2.bin")?,
+///     ];
+///     let file = File::create("/path/to/merged.bin").unwrap();
+///
+///     let (_, report) = transform::merge(readers, BufWriter::new(file))?;
+///     for gap in report.min_gap(1_000_000).gaps() {
+///         println!("Gap of {} cycles", gap.duration());
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+pub fn merge<R, I, W>(readers: I, writer: W) -> Result<(W, MergeReport)>
+where
+    R: io::Read,
+    I: IntoIterator<Item = RecordReader<R>>,
+    W: io::Write,
+{
+    let mut writer = TraceWriter::new(writer).preserve_domains(true);
+    let mut inputs = readers.into_iter().map(Input::new).collect::<Vec<_>>();
+    let mut spans = vec![None; inputs.len()];
+
+    loop {
+        for input in inputs.iter_mut() {
+            if input.is_empty() {
+                input.pull()?;
+            }
+        }
+
+        let next = inputs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, input)| input.heads().map(move |(cpu, tsc)| (tsc, i, cpu)))
+            .min();
+
+        let Some((tsc, index, cpu)) = next else {
+            break;
+        };
+
+        // Another reader may still have an earlier record on the same CPU
+        let mut pulled = false;
+        for input in inputs.iter_mut() {
+            while input.head(cpu).is_none() && input.pull()? {
+                pulled = true;
+            }
+        }
+
+        if pulled {
+            continue;
+        }
+
+        // Drain the CPU to keep its segment going, reading ahead only
+        // the readers still on the same CPU
+        let (mut tsc, mut index) = (tsc, index);
+        loop {
+            let span: &mut Option<(u64, u64)> = &mut spans[index];
+            *span = Some(span.map_or((tsc, tsc), |(first, last)| (first.min(tsc), last.max(tsc))));
+
+            if let Some(record) = inputs[index].pop(cpu) {
+                writer.write_record(&record)?;
+            }
+
+            let mut blocked = false;
+            for input in inputs.iter_mut() {
+                while input.head(cpu).is_none() && input.last_cpu == Some(cpu) && input.pull()? {}
+                blocked |= input.head(cpu).is_none() && !input.done;
+            }
+
+            let next = inputs
+                .iter()
+                .enumerate()
+                .filter_map(|(i, input)| input.head(cpu).map(|r| (r.event.tsc, i)))
+                .min();
+
+            match next {
+                Some(next) if !blocked => (tsc, index) = next,
+                _ => break,
+            }
+        }
+    }
+
+    writer.finish().map(|w| (w, MergeReport::from_spans(spans)))
+}
+
+/// A reader being merged, with the records read ahead for each CPU.
+struct Input<R> {
+    reader: RecordReader<R>,
+    cpus: BTreeMap<u32, VecDeque<Record>>,
+    last_cpu: Option<u32>,
+    done: bool,
+}
+
+impl<R: io::Read> Input<R> {
+    fn new(reader: RecordReader<R>) -> Self {
+        Self {
+            reader,
+            cpus: BTreeMap::new(),
+            last_cpu: None,
+            done: false,
+        }
+    }
+
+    /// Reads the next record, returning `false` if there are no more records.
+    fn pull(&mut self) -> Result<bool> {
+        if self.done {
+            return Ok(false);
+        }
+
+        match self.reader.next().transpose()? {
+            Some(record) => {
+                self.last_cpu = Some(record.cpu);
+                self.cpus.entry(record.cpu).or_default().push_back(record);
+                Ok(true)
+            }
+            None => {
+                self.done = true;
+                Ok(false)
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.cpus.values().all(VecDeque::is_empty)
+    }
+
+    /// Returns the `(cpu, tsc)` of the next record of each CPU.
+    fn heads(&self) -> impl Iterator<Item = (u32, u64)> + '_ {
+        self.cpus
+            .iter()
+            .filter_map(|(cpu, records)| records.front().map(|r| (*cpu, r.event.tsc)))
+    }
+
+    fn head(&self, cpu: u32) -> Option<&Record> {
+        self.cpus.get(&cpu).and_then(VecDeque::front)
+    }
+
+    fn pop(&mut self, cpu: u32) -> Option<Record> {
+        self.cpus.get_mut(&cpu).and_then(VecDeque::pop_front)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, MergeReport};
+    use crate::{
+        record::{Domain, DomainKind},
+        trc::TRC_SCHED_CONTINUE_RUNNING,
+        RecordReader, Trace, TraceBuilder,
+    };
+
+    #[test]
+    fn report_test() {
+        let report =
+            MergeReport::from_spans(vec![Some((50, 80)), None, Some((0, 10)), Some((70, 90))]);
+
+        assert_eq!(report.overlaps().len(), 1);
+        assert_eq!(report.overlaps()[0].traces(), (0, 3));
+        assert_eq!(report.overlaps()[0].start_tsc(), 70);
+        assert_eq!(report.overlaps()[0].end_tsc(), 80);
+        assert_eq!(report.gaps().len(), 1);
+        assert_eq!(report.gaps()[0].start_tsc(), 10);
+        assert_eq!(report.gaps()[0].duration(), 40);
+        assert_eq!(report.clone().min_gap(40).gaps().len(), 1);
+        assert!(report.min_gap(41).gaps().is_empty());
+    }
+
+    #[test]
+    fn merge_test() {
+        let dom1 = Domain::new(DomainKind::Guest(1), 0);
+        let dom2 = Domain::new(DomainKind::Guest(2), 0);
+
+        let first = TraceBuilder::new()
+            .schedule(10, dom1)
+            .event(20, 0x00081001, &[]);
+        let second = TraceBuilder::new()
+            .schedule(100, dom2)
+            .event(110, 0x00081001, &[]);
+
+        let (merged, report) = Trace::merge([first.build().unwrap(), second.build().unwrap()]);
+        assert_eq!(merged.record_count(), 4);
+        assert_eq!(merged[3].domain(), &dom2);
+        assert_eq!(report.gaps()[0].duration(), 80);
+
+        let first = first.to_bytes().unwrap();
+        let second = second.to_bytes().unwrap();
+        let readers = [first.as_slice(), second.as_slice()].map(RecordReader::new);
+
+        let (bytes, streamed_report) = merge(readers, Vec::new()).unwrap();
+        let streamed = Trace::from_bytes(bytes).unwrap();
+
+        assert_eq!(streamed_report, report);
+        assert_eq!(&streamed[..], &merged[..]);
+    }
+
+    #[test]
+    fn merge_cpus_test() {
+        let dom1 = Domain::new(DomainKind::Guest(1), 0);
+        let dom2 = Domain::new(DomainKind::Guest(2), 0);
+
+        // Segments of both traces are not sorted by TSC across CPUs
+        let first = TraceBuilder::new()
+            .schedule(10, dom1)
+            .event(20, 0x00081001, &[])
+            .event(300, 0x00081001, &[])
+            .cpu(1)
+            .event(100, 0x00081001, &[])
+            .event(200, 0x00081001, &[]);
+        let second = TraceBuilder::new()
+            .cpu(1)
+            .schedule(50, dom2)
+            .event(150, 0x00081001, &[])
+            .cpu(0)
+            .event(30, 0x00081001, &[])
+            .event(250, 0x00081001, &[]);
+
+        let (merged, report) = Trace::merge([first.build().unwrap(), second.build().unwrap()]);
+
+        let first = first.to_bytes().unwrap();
+        let second = second.to_bytes().unwrap();
+        let readers = [first.as_slice(), second.as_slice()].map(RecordReader::new);
+
+        let (bytes, streamed_report) = merge(readers, Vec::new()).unwrap();
+
+        let mut last_tsc = [0; 2];
+        for record in RecordReader::new(bytes.as_slice()) {
+            let record = record.unwrap();
+            let last_tsc = &mut last_tsc[record.cpu() as usize];
+
+            assert!(record.event().tsc() >= *last_tsc);
+            *last_tsc = record.event().tsc();
+        }
+
+        let streamed = Trace::from_bytes(bytes).unwrap();
+        assert_eq!(streamed_report, report);
+
+        // Leaving out the records restoring the domain of the CPUs
+        let streamed = streamed
+            .iter()
+            .filter(|r| r.event().code() != TRC_SCHED_CONTINUE_RUNNING)
+            .collect::<Vec<_>>();
+        assert_eq!(streamed, merged.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn merge_segments_test() {
+        let mut first = TraceBuilder::new();
+        let mut second = TraceBuilder::new().cpu(1);
+        for tsc in 0..10 {
+            first = first.event(tsc * 10, 0x00081001, &[]);
+            second = second.event(tsc * 10 + 5, 0x00081001, &[]);
+        }
+
+        let first = first.to_bytes().unwrap();
+        let second = second.to_bytes().unwrap();
+        let readers = [first.as_slice(), second.as_slice()].map(RecordReader::new);
+
+        // Not a segment for each record, as many as the merged traces
+        let (bytes, _) = merge(readers, Vec::new()).unwrap();
+        assert_eq!(bytes.len(), first.len() + second.len());
+        assert_eq!(Trace::from_bytes(bytes).unwrap().record_count(), 20);
+    }
+}
@@ -0,0 +1,24 @@
+//! Streaming transformations of XenTrace binary files.
+//!
+//! Records are read through a [`RecordReader`](crate::RecordReader) and
+//! written back as XenTrace binary data, preserving their domains (see
+//! [`TraceWriter::preserve_domains`](crate::TraceWriter::preserve_domains)).
+//!
+//! # Synthetic records
+//!
+//! As the readers track the domain of a record through the scheduling events
+//! of its CPU, a `TRC_SCHED_CONTINUE_RUNNING` record (code `0x00021002`, with
+//! the domain as its first extra value) is written before any record whose
+//! domain would otherwise be lost, with the same TSC value (*e.g.* before the
+//! first record of each CPU of a slice). The written traces may thus hold
+//! more records than the ones read.
+
+mod merge;
+mod slice;
+mod split;
+
+pub use self::{
+    merge::{merge, Gap, MergeReport, Overlap},
+    slice::slice_time,
+    split::{split, SplitBy, SplitKey},
+};
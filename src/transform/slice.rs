@@ -0,0 +1,98 @@
+use std::io;
+
+use crate::{RecordReader, Result, TraceWriter};
+
+/// Writes the records read by `reader` whose TSC value is in the range
+/// from `start` (inclusive) to `end` (exclusive) to `writer`, as XenTrace
+/// binary data, returning the underlying writer.
+///
+/// If `cpus` is given, only the records of those CPUs are written.
+/// The domain of each CPU at the start of the slice is preserved, adding
+/// [synthetic records](crate::transform#synthetic-records).
+///
+/// # Errors
+///
+/// This function will return an error if it fails to read or write a record.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::{fs::File, io::BufWriter};
+/// use xentrace_parser::{transform, RecordReader, Result};
+///
+/// fn main() -> Result<()> {
+///     let reader = RecordReader::from_file("/path/to/xentrace.bin")?;
+///     let file = File::create("/path/to/slice.bin").unwrap();
+///
+///     transform::slice_time(reader, BufWriter::new(file), 1_000_000, 2_000_000, Some(&[0, 1]))?;
+///     Ok(())
+/// }
+/// ```
+pub fn slice_time<R: io::Read, W: io::Write>(
+    reader: RecordReader<R>,
+    writer: W,
+    start: u64,
+    end: u64,
+    cpus: Option<&[u32]>,
+) -> Result<W> {
+    let mut writer = TraceWriter::new(writer).preserve_domains(true);
+
+    for record in reader {
+        let record = record?;
+
+        let in_range = (start..end).contains(&record.event.tsc);
+        let in_cpus = cpus.map_or(true, |cpus| cpus.contains(&record.cpu));
+
+        if in_range && in_cpus {
+            writer.write_record(&record)?;
+        }
+    }
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::slice_time;
+    use crate::{
+        record::{Domain, DomainKind},
+        RecordReader, Trace, TraceBuilder,
+    };
+
+    #[test]
+    fn slice_test() {
+        let dom1 = Domain::new(DomainKind::Guest(1), 0);
+        let dom2 = Domain::new(DomainKind::Guest(2), 0);
+
+        let bytes = TraceBuilder::new()
+            .schedule(10, dom1)
+            .event(20, 0x00081001, &[])
+            .event(30, 0x00081001, &[])
+            .cpu(1)
+            .schedule(15, dom2)
+            .event(25, 0x00081001, &[])
+            .event(35, 0x00081001, &[])
+            .to_bytes()
+            .unwrap();
+
+        let trace = Trace::from_bytes(&bytes).unwrap();
+        let expected = trace.slice_time(20, 35, None).collect::<Vec<_>>();
+        assert_eq!(expected.len(), 3);
+
+        let reader = RecordReader::new(bytes.as_slice());
+        let sliced = slice_time(reader, Vec::new(), 20, 35, None).unwrap();
+        let sliced = Trace::from_bytes(sliced).unwrap();
+
+        // Each CPU starts with a marker of its domain
+        assert_eq!(sliced.record_count(), 5);
+        assert_eq!(&sliced[1], expected[0]);
+        assert!(sliced[3..].iter().eq(expected[1..].iter().copied()));
+
+        let reader = RecordReader::new(bytes.as_slice());
+        let sliced = slice_time(reader, Vec::new(), 0, u64::MAX, Some(&[1])).unwrap();
+        let sliced = Trace::from_bytes(sliced).unwrap();
+
+        assert!(sliced.iter().all(|r| r.cpu() == 1 && r.domain() == &dom2));
+        assert!(sliced.iter().eq(trace.slice_time(0, u64::MAX, Some(&[1]))));
+    }
+}
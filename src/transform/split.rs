@@ -0,0 +1,191 @@
+use std::{collections::HashMap, io};
+
+use fxhash::FxBuildHasher;
+
+use crate::{record::DomainKind, writer::encoded_len, Error, RecordReader, Result, TraceWriter};
+
+/// The criterion used to [split](split) a trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitBy {
+    /// One part for each domain (with all its virtual processors).
+    Domain,
+    /// One part for each physical CPU.
+    Cpu,
+    /// Consecutive parts of about the given number of bytes each.
+    Size(u64),
+}
+
+/// The key identifying a part of a [split](split) trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SplitKey {
+    /// The part holding the records of a domain.
+    Domain(DomainKind),
+    /// The part holding the records of a physical CPU.
+    Cpu(u32),
+    /// The part holding the n-th chunk of records.
+    Chunk(usize),
+}
+
+/// Splits the records read by `reader` into several parts, according to `by`,
+/// each one written as XenTrace binary data to the writer returned by `create`
+/// for its key, returning the underlying writers in order of creation.
+///
+/// Each part can be read back independently: the domain of each record is
+/// preserved, adding [synthetic records](crate::transform#synthetic-records)
+/// (not counted in the size of a part). With [`SplitBy::Size`], a part is
+/// completed as soon as its records reach the given size, so all parts
+/// except the last one are slightly larger than it.
+///
+/// # Errors
+///
+/// This function will return an error if it fails to read or write a record,
+/// or if `create` fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::{fs::File, io::BufWriter};
+/// use xentrace_parser::{
+///     transform::{self, SplitBy, SplitKey},
+///     RecordReader, Result,
+/// };
+///
+/// fn main() -> Result<()> {
+///     let reader = RecordReader::from_file("/path/to/xentrace.bin")?;
+///
+///     transform::split(reader, SplitBy::Cpu, |key| {
+///         let path = match key {
+///             SplitKey::Cpu(cpu) => format!("/path/to/xentrace-cpu{}.bin", cpu),
+///             _ => unreachable!(),
+///         };
+///
+///         File::create(path).map(BufWriter::new)
+///     })?;
+///
+///     Ok(())
+/// }
+/// ```
+pub fn split<R, W, F>(
+    reader: RecordReader<R>,
+    by: SplitBy,
+    mut create: F,
+) -> Result<Vec<(SplitKey, W)>>
+where
+    R: io::Read,
+    W: io::Write,
+    F: FnMut(SplitKey) -> io::Result<W>,
+{
+    let mut finished = Vec::new();
+    let mut parts = Vec::<(SplitKey, TraceWriter<W>)>::new();
+    let mut indices = HashMap::<SplitKey, usize, FxBuildHasher>::default();
+
+    let mut chunk = 0;
+    let mut chunk_len = 0;
+
+    for record in reader {
+        let record = record?;
+
+        let key = match by {
+            SplitBy::Domain => SplitKey::Domain(record.domain.kind),
+            SplitBy::Cpu => SplitKey::Cpu(record.cpu),
+            SplitBy::Size(size) => {
+                // Only the current chunk is being written
+                if chunk_len > 0 && chunk_len >= size {
+                    if let Some((key, writer)) = parts.pop() {
+                        indices.remove(&key);
+                        finished.push((key, writer.finish()?));
+                    }
+
+                    chunk += 1;
+                    chunk_len = 0;
+                }
+
+                chunk_len += encoded_len(&record.event) as u64;
+                SplitKey::Chunk(chunk)
+            }
+        };
+
+        let index = match indices.get(&key) {
+            Some(index) => *index,
+            None => {
+                let writer =
+                    create(key).map_err(|e| Error::io_error("Failed to create trace part", e))?;
+
+                parts.push((key, TraceWriter::new(writer).preserve_domains(true)));
+                indices.insert(key, parts.len() - 1);
+                parts.len() - 1
+            }
+        };
+
+        parts[index].1.write_record(&record)?;
+    }
+
+    for (key, writer) in parts {
+        finished.push((key, writer.finish()?));
+    }
+
+    Ok(finished)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split, SplitBy, SplitKey};
+    use crate::{
+        record::{Domain, DomainKind},
+        RecordReader, Trace, TraceBuilder,
+    };
+
+    #[test]
+    fn split_by_domain_test() {
+        let dom1 = Domain::new(DomainKind::Guest(1), 0);
+        let dom2 = Domain::new(DomainKind::Guest(2), 1);
+
+        let bytes = TraceBuilder::new()
+            .schedule(10, dom1)
+            .event(20, 0x00081001, &[])
+            .schedule(30, dom2)
+            .event(40, 0x00081001, &[])
+            .cpu(1)
+            .schedule(15, dom1)
+            .event(25, 0x00081001, &[])
+            .to_bytes()
+            .unwrap();
+
+        let reader = RecordReader::new(bytes.as_slice());
+        let parts = split(reader, SplitBy::Domain, |_| Ok(Vec::new())).unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].0, SplitKey::Domain(DomainKind::Guest(1)));
+
+        let first = Trace::from_bytes(&parts[0].1).unwrap();
+        let second = Trace::from_bytes(&parts[1].1).unwrap();
+
+        // "running -> runnable" of dom1 included
+        assert_eq!(first.record_count(), 5);
+        assert!(first.iter().all(|r| r.domain() == &dom1));
+        // "runnable -> running" of dom2 sets the domain, no marker needed
+        assert_eq!(second.record_count(), 2);
+        assert!(second.iter().all(|r| r.domain() == &dom2));
+    }
+
+    #[test]
+    fn split_by_size_test() {
+        let mut builder = TraceBuilder::new();
+        for tsc in 0..10 {
+            builder = builder.event(tsc, 0x00081001, &[]);
+        }
+
+        let bytes = builder.to_bytes().unwrap();
+        let reader = RecordReader::new(bytes.as_slice());
+        let parts = split(reader, SplitBy::Size(36), |_| Ok(Vec::new())).unwrap();
+
+        let keys = parts.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, (0..4).map(SplitKey::Chunk).collect::<Vec<_>>());
+
+        let counts = parts
+            .iter()
+            .map(|(_, bytes)| Trace::from_bytes(bytes).unwrap().record_count())
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [3, 3, 3, 1]);
+    }
+}
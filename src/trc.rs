@@ -0,0 +1,330 @@
+// Event codes and tables from Xen's public "trace.h" header
+// (and the hypercall numbers of "xen.h").
+
+// Trace classes
+pub(crate) const TRC_GEN: u32 = 0x0001F000;
+pub(crate) const TRC_SCHED_MIN: u32 = 0x00021000;
+pub(crate) const TRC_HVM_ENTRYEXIT: u32 = 0x00081000;
+pub(crate) const TRC_HVM_HANDLER: u32 = 0x00082000;
+pub(crate) const TRC_PV_ENTRY: u32 = 0x00201000;
+pub(crate) const TRC_PV_SUBCALL: u32 = 0x00202000;
+
+/// Flag set on events that carry 64-bit guest addresses.
+pub(crate) const TRC_64_FLAG: u32 = 0x100;
+
+// Generic events
+pub(crate) const TRC_LOST_RECORDS: u32 = TRC_GEN + 1;
+pub(crate) const TRC_TRACE_CPU_CHANGE: u32 = TRC_GEN + 3;
+
+// Scheduler events
+pub(crate) const TRC_SCHED_RUNSTATE_CHANGE: u32 = TRC_SCHED_MIN + 1;
+pub(crate) const TRC_SCHED_CONTINUE_RUNNING: u32 = TRC_SCHED_MIN + 2;
+
+/// Mask of the old/new runstate bits packed into `TRC_SCHED_RUNSTATE_CHANGE` events.
+pub(crate) const TRC_SCHED_RUNSTATE_MASK: u32 = 0x00000FF0;
+
+/// Mask matching the scheduler events that put a vCPU in the running state.
+pub(crate) const TRC_SCHED_TO_RUN: u32 = 0x00021F0F;
+
+// HVM events
+pub(crate) const TRC_HVM_VMENTRY: u32 = TRC_HVM_ENTRYEXIT + 0x01;
+pub(crate) const TRC_HVM_VMEXIT: u32 = TRC_HVM_ENTRYEXIT + 0x02;
+pub(crate) const TRC_HVM_VMEXIT64: u32 = TRC_HVM_VMEXIT + TRC_64_FLAG;
+pub(crate) const TRC_HVM_HYPERCALL: u32 = TRC_HVM_HANDLER + 0x12;
+
+// PV events
+pub(crate) const TRC_PV_HYPERCALL: u32 = TRC_PV_ENTRY + 0x01;
+pub(crate) const TRC_PV_HYPERCALL64: u32 = TRC_PV_HYPERCALL + TRC_64_FLAG;
+pub(crate) const TRC_PV_HYPERCALL_V2: u32 = TRC_PV_ENTRY + 0x0D;
+pub(crate) const TRC_PV_HYPERCALL_SUBCALL: u32 = TRC_PV_SUBCALL + 0x0E;
+
+/// Mask of the argument bits packed with the operation of a `TRC_PV_HYPERCALL_V2` event.
+pub(crate) const TRC_PV_HYPERCALL_V2_ARG_MASK: u32 = 0xFFF00000;
+
+/// Returns the name of the vCPU runstate packed into `TRC_SCHED_RUNSTATE_CHANGE` events.
+pub(crate) fn runstate_name(state: u32) -> Option<&'static str> {
+    match state {
+        0 => Some("running"),
+        1 => Some("runnable"),
+        2 => Some("blocked"),
+        3 => Some("offline"),
+        _ => None,
+    }
+}
+
+/// Returns the name of the hypercall identified by `op`, if known.
+pub(crate) fn hypercall_name(op: u32) -> Option<&'static str> {
+    const NAMES: [&str; 43] = [
+        "set_trap_table",
+        "mmu_update",
+        "set_gdt",
+        "stack_switch",
+        "set_callbacks",
+        "fpu_taskswitch",
+        "sched_op_compat",
+        "platform_op",
+        "set_debugreg",
+        "get_debugreg",
+        "update_descriptor",
+        "",
+        "memory_op",
+        "multicall",
+        "update_va_mapping",
+        "set_timer_op",
+        "event_channel_op_compat",
+        "xen_version",
+        "console_io",
+        "physdev_op_compat",
+        "grant_table_op",
+        "vm_assist",
+        "update_va_mapping_otherdomain",
+        "iret",
+        "vcpu_op",
+        "set_segment_base",
+        "mmuext_op",
+        "xsm_op",
+        "nmi_op",
+        "sched_op",
+        "callback_op",
+        "xenoprof_op",
+        "event_channel_op",
+        "physdev_op",
+        "hvm_op",
+        "sysctl",
+        "domctl",
+        "kexec_op",
+        "tmem_op",
+        "argo_op",
+        "xenpmu_op",
+        "dm_op",
+        "hypfs_op",
+    ];
+
+    match op {
+        48 => Some("mca"),
+        _ => NAMES
+            .get(op as usize)
+            .copied()
+            .filter(|name| !name.is_empty()),
+    }
+}
+
+/// Returns the name of the VMX basic exit `reason`, if known.
+pub(crate) fn vmx_exit_reason_name(reason: u32) -> Option<&'static str> {
+    const NAMES: [&str; 65] = [
+        "EXCEPTION_NMI",
+        "EXTERNAL_INTERRUPT",
+        "TRIPLE_FAULT",
+        "INIT",
+        "SIPI",
+        "IO_SMI",
+        "OTHER_SMI",
+        "PENDING_VIRT_INTR",
+        "PENDING_VIRT_NMI",
+        "TASK_SWITCH",
+        "CPUID",
+        "GETSEC",
+        "HLT",
+        "INVD",
+        "INVLPG",
+        "RDPMC",
+        "RDTSC",
+        "RSM",
+        "VMCALL",
+        "VMCLEAR",
+        "VMLAUNCH",
+        "VMPTRLD",
+        "VMPTRST",
+        "VMREAD",
+        "VMRESUME",
+        "VMWRITE",
+        "VMXOFF",
+        "VMXON",
+        "CR_ACCESS",
+        "DR_ACCESS",
+        "IO_INSTRUCTION",
+        "MSR_READ",
+        "MSR_WRITE",
+        "INVALID_GUEST_STATE",
+        "MSR_LOADING",
+        "",
+        "MWAIT_INSTRUCTION",
+        "MONITOR_TRAP_FLAG",
+        "",
+        "MONITOR_INSTRUCTION",
+        "PAUSE_INSTRUCTION",
+        "MCE_DURING_VMENTRY",
+        "",
+        "TPR_BELOW_THRESHOLD",
+        "APIC_ACCESS",
+        "EOI_INDUCED",
+        "ACCESS_GDTR_OR_IDTR",
+        "ACCESS_LDTR_OR_TR",
+        "EPT_VIOLATION",
+        "EPT_MISCONFIG",
+        "INVEPT",
+        "RDTSCP",
+        "VMX_PREEMPTION_TIMER_EXPIRED",
+        "INVVPID",
+        "WBINVD",
+        "XSETBV",
+        "APIC_WRITE",
+        "RDRAND",
+        "INVPCID",
+        "VMFUNC",
+        "ENCLS",
+        "RDSEED",
+        "PML_FULL",
+        "XSAVES",
+        "XRSTORS",
+    ];
+
+    NAMES
+        .get(reason as usize)
+        .copied()
+        .filter(|name| !name.is_empty())
+}
+
+/// Returns the class (the `main` part of the event codes) named `name`, if known.
+pub(crate) fn class_by_name(name: &str) -> Option<u32> {
+    match name {
+        "gen" => Some(0x0001),
+        "sched" => Some(0x0002),
+        "dom0op" => Some(0x0004),
+        "hvm" => Some(0x0008),
+        "mem" => Some(0x0010),
+        "pv" => Some(0x0020),
+        "shadow" => Some(0x0040),
+        "hw" => Some(0x0080),
+        "guest" => Some(0x0800),
+        _ => None,
+    }
+}
+
+/// Names of the known events (sorted by code), as in the "formats" file of
+/// `xentrace_format` (with a `64` suffix for the variants carrying 64-bit
+/// guest addresses).
+const EVENT_NAMES: [(u32, &str); 105] = [
+    (0x0001F001, "lost_records"),
+    (0x0001F002, "wrap_buffer"),
+    (0x0001F003, "cpu_change"),
+    (0x0001F004, "trace_irq"),
+    (0x00021002, "continue_running"),
+    (0x00021011, "running_to_runnable"),
+    (0x00021021, "running_to_blocked"),
+    (0x00021031, "running_to_offline"),
+    (0x00021101, "runnable_to_running"),
+    (0x00021121, "runnable_to_blocked"),
+    (0x00021131, "runnable_to_offline"),
+    (0x00021201, "blocked_to_running"),
+    (0x00021211, "blocked_to_runnable"),
+    (0x00021231, "blocked_to_offline"),
+    (0x00021301, "offline_to_running"),
+    (0x00021311, "offline_to_runnable"),
+    (0x00021321, "offline_to_blocked"),
+    (0x00022201, "csched2:tick"),
+    (0x00022202, "csched2:runq_pos"),
+    (0x00022203, "csched2:credit_burn"),
+    (0x00022204, "csched2:credit_add"),
+    (0x00022205, "csched2:tickle_check"),
+    (0x00022206, "csched2:tickle"),
+    (0x00022207, "csched2:credit_reset"),
+    (0x00022208, "csched2:sched_tasklet"),
+    (0x00022209, "csched2:update_load"),
+    (0x0002220A, "csched2:runq_assign"),
+    (0x0002220B, "csched2:updt_vcpu_load"),
+    (0x0002220C, "csched2:updt_runq_load"),
+    (0x0002220D, "csched2:tickle_new"),
+    (0x0002220E, "csched2:runq_max_weight"),
+    (0x0002220F, "csched2:migrate"),
+    (0x00022210, "csched2:load_check"),
+    (0x00022211, "csched2:load_balance"),
+    (0x00022213, "csched2:pick_cpu"),
+    (0x00022214, "csched2:runq_candidate"),
+    (0x00022215, "csched2:schedule"),
+    (0x00022216, "csched2:ratelimit"),
+    (0x00022217, "csched2:runq_cand_chk"),
+    (0x00028001, "sched_add_domain"),
+    (0x00028002, "sched_rem_domain"),
+    (0x00028003, "domain_sleep"),
+    (0x00028004, "domain_wake"),
+    (0x00028005, "do_yield"),
+    (0x00028006, "do_block"),
+    (0x00028007, "domain_shutdown"),
+    (0x00028008, "sched_ctl"),
+    (0x00028009, "sched_adjdom"),
+    (0x0002800A, "__enter_scheduler"),
+    (0x0002800B, "s_timer_fn"),
+    (0x0002800C, "t_timer_fn"),
+    (0x0002800D, "dom_timer_fn"),
+    (0x0002800E, "switch_infprev"),
+    (0x0002800F, "switch_infnext"),
+    (0x00028010, "domain_shutdown_code"),
+    (0x00028011, "switch_infcont"),
+    (0x00040001, "domain_create"),
+    (0x00040002, "domain_destroy"),
+    (0x00081001, "VMENTRY"),
+    (0x00081002, "VMEXIT"),
+    (0x00081102, "VMEXIT64"),
+    (0x00082001, "PF_XEN"),
+    (0x00082002, "PF_INJECT"),
+    (0x00082003, "INJ_EXC"),
+    (0x00082004, "INJ_VIRQ"),
+    (0x00082005, "REINJ_VIRQ"),
+    (0x00082006, "IO_READ"),
+    (0x00082007, "IO_WRITE"),
+    (0x00082008, "CR_READ"),
+    (0x00082009, "CR_WRITE"),
+    (0x0008200A, "DR_READ"),
+    (0x0008200B, "DR_WRITE"),
+    (0x0008200C, "MSR_READ"),
+    (0x0008200D, "MSR_WRITE"),
+    (0x0008200E, "CPUID"),
+    (0x0008200F, "INTR"),
+    (0x00082010, "NMI"),
+    (0x00082011, "SMI"),
+    (0x00082012, "VMMCALL"),
+    (0x00082013, "HLT"),
+    (0x00082014, "INVLPG"),
+    (0x00082015, "MCE"),
+    (0x00082016, "IOPORT_READ"),
+    (0x00082017, "MMIO_READ"),
+    (0x00082018, "CLTS"),
+    (0x00082019, "LMSW"),
+    (0x0008201A, "RDTSC"),
+    (0x00082020, "INTR_WINDOW"),
+    (0x00082021, "NPF"),
+    (0x00082101, "PF_XEN64"),
+    (0x00082102, "PF_INJECT64"),
+    (0x00082108, "CR_READ64"),
+    (0x00082109, "CR_WRITE64"),
+    (0x00082114, "INVLPG64"),
+    (0x00082119, "LMSW64"),
+    (0x00082216, "IOPORT_WRITE"),
+    (0x00082217, "MMIO_WRITE"),
+    (0x0008400E, "pic_posedge"),
+    (0x0008400F, "pic_negedge"),
+    (0x00084010, "pic_pend_irq_call"),
+    (0x00084011, "lapic_pic_intr"),
+    (0x00201001, "hypercall"),
+    (0x0020100D, "hypercall_v2"),
+    (0x00201101, "hypercall64"),
+    (0x0020200E, "hypercall_subcall"),
+];
+
+/// Returns the name of the event identified by `code`, if known.
+pub(crate) fn event_name(code: u32) -> Option<&'static str> {
+    EVENT_NAMES
+        .binary_search_by_key(&code, |(c, _)| *c)
+        .ok()
+        .map(|i| EVENT_NAMES[i].1)
+}
+
+/// Returns the code of the event named `name`, if known.
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
+pub(crate) fn event_by_name(name: &str) -> Option<u32> {
+    EVENT_NAMES
+        .iter()
+        .find(|(_, n)| *n == name)
+        .map(|(code, _)| *code)
+}
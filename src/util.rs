@@ -1,4 +1,4 @@
-pub(crate) use self::reader::IoReadUtil;
+pub(crate) use self::{counter::CountingReader, reader::IoReadUtil, writer::IoWriteUtil};
 
 mod reader {
     use std::io;
@@ -24,3 +24,53 @@ mod reader {
         }
     }
 }
+
+mod writer {
+    use std::io;
+
+    pub trait IoWriteUtil {
+        fn write_ne_u32(&mut self, value: u32) -> io::Result<()>;
+        fn write_ne_u64(&mut self, value: u64) -> io::Result<()>;
+    }
+
+    impl<W: io::Write> IoWriteUtil for W {
+        #[inline]
+        fn write_ne_u32(&mut self, value: u32) -> io::Result<()> {
+            self.write_all(&value.to_ne_bytes()) // host endian because of XenTrace
+        }
+
+        #[inline]
+        fn write_ne_u64(&mut self, value: u64) -> io::Result<()> {
+            self.write_all(&value.to_ne_bytes()) // host endian because of XenTrace
+        }
+    }
+}
+
+mod counter {
+    use std::io;
+
+    /// Reader that keeps track of the number of bytes read.
+    #[derive(Debug)]
+    pub struct CountingReader<R> {
+        inner: R,
+        count: u64,
+    }
+
+    impl<R> CountingReader<R> {
+        pub fn new(inner: R) -> Self {
+            Self { inner, count: 0 }
+        }
+
+        pub fn count(&self) -> u64 {
+            self.count
+        }
+    }
+
+    impl<R: io::Read> io::Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.inner.read(buf)?;
+            self.count += len as u64;
+            Ok(len)
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::Path,
+};
+
+use fxhash::FxBuildHasher;
+
+use crate::{
+    record::{Domain, Event, Record, EVENT_EXTRA_CAPACITY},
+    trc::{TRC_SCHED_CONTINUE_RUNNING, TRC_SCHED_TO_RUN, TRC_TRACE_CPU_CHANGE},
+    util::IoWriteUtil,
+    Error, Result,
+};
+
+/// The maximum length (in bytes) of a segment, as the per-CPU buffers of `xentrace`.
+const SEGMENT_LEN: usize = 64 * 1024;
+
+/// Writer of XenTrace binary files.
+///
+/// Records are grouped in segments, each one introduced by a
+/// `TRC_TRACE_CPU_CHANGE` record holding the CPU and the length
+/// (in bytes) of the segment, as `xentrace` does. The written data
+/// can be read back by [`Trace`] and by the Xen tools (`xenalyze`,
+/// `xentrace_format`).
+///
+/// A segment is buffered until the CPU changes or it reaches 64 KiB,
+/// so [`finish`](TraceWriter::finish) (or [`flush`](TraceWriter::flush))
+/// must be called: the records of the current segment are lost if the
+/// writer is dropped.
+///
+/// **Note:** The domain of a record is not stored in the file, it is
+/// tracked by the readers through the scheduling events of each CPU.
+/// See [`preserve_domains`](TraceWriter::preserve_domains) to keep the
+/// domains of filtered or sliced records.
+///
+/// # Examples
+///
+/// ```no_run
+/// use xentrace_parser::{Result, Trace, TraceWriter};
+///
+/// fn main() -> Result<()> {
+///     let trace = Trace::from_file("/path/to/xentrace.bin")?;
+///
+///     // Keep only the HVM events
+///     let mut writer = TraceWriter::create("/path/to/hvm.bin")?;
+///     for record in trace.iter().filter(|r| r.event().code().main() == 0x0008) {
+///         writer.write_record(record)?;
+///     }
+///     writer.finish()?;
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TraceWriter<W: io::Write> {
+    wtr: W,
+    cpu: Option<u32>,
+    segment: Vec<u8>,
+    domains: Option<HashMap<u32, Domain, FxBuildHasher>>,
+}
+
+impl TraceWriter<io::BufWriter<fs::File>> {
+    /// Constructs a `TraceWriter` to a file specified by its path,
+    /// which is created (or truncated).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to create the trace file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        fs::File::create(path)
+            .map_err(|e| Error::io_error("Failed to create trace file", e))
+            .map(io::BufWriter::new)
+            .map(Self::new)
+    }
+}
+
+impl<W: io::Write> TraceWriter<W> {
+    /// Constructs a `TraceWriter` to any type that implements `io::Write`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            wtr: writer,
+            cpu: None,
+            segment: Vec::with_capacity(SEGMENT_LEN),
+            domains: None,
+        }
+    }
+
+    /// Sets whether the domain of each written record must be preserved.
+    ///
+    /// When enabled, a `TRC_SCHED_CONTINUE_RUNNING` record is written
+    /// before any record whose domain would not be tracked by the readers
+    /// (*e.g.* the first record of each CPU of a slice of a trace), so that
+    /// reading the written data yields the same domains.
+    pub fn preserve_domains(mut self, preserve: bool) -> Self {
+        self.domains = preserve.then(HashMap::default);
+        self
+    }
+
+    /// Writes a single record.
+    ///
+    /// A new segment is started whenever the CPU of the record
+    /// differs from the one of the previous record.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the record is a
+    /// `TRC_TRACE_CPU_CHANGE` one or if it fails to write a segment.
+    pub fn write_record(&mut self, record: &Record) -> Result<()> {
+        if record.event.code == TRC_TRACE_CPU_CHANGE {
+            return Err(Error::new(
+                "Failed to write record: CPU change records are reserved",
+            ));
+        }
+
+        if let Some(marker) = self.domain_marker(record) {
+            self.write_event(record.cpu, &marker)?;
+        }
+
+        self.write_event(record.cpu, &record.event)
+    }
+
+    /// Writes all the records of a [`Trace`](crate::Trace) (or of a slice of it),
+    /// writing the records of each CPU in consecutive segments.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write a segment.
+    pub fn write_trace(&mut self, records: &[Record]) -> Result<()> {
+        let mut cpus = BTreeMap::<u32, Vec<&Record>>::new();
+        for record in records {
+            cpus.entry(record.cpu).or_default().push(record);
+        }
+
+        for record in cpus.into_values().flatten() {
+            self.write_record(record)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the current segment and flushes the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write the segment.
+    pub fn flush(&mut self) -> Result<()> {
+        self.flush_segment()?;
+        self.wtr
+            .flush()
+            .map_err(|e| Error::io_error("Failed to flush trace writer", e))
+    }
+
+    /// Writes the current segment and returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it fails to write the segment.
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.wtr)
+    }
+
+    fn write_event(&mut self, cpu: u32, event: &Event) -> Result<()> {
+        let len = encoded_len(event);
+        if self.cpu != Some(cpu) || self.segment.len() + len > SEGMENT_LEN {
+            self.flush_segment()?;
+            self.cpu = Some(cpu);
+        }
+
+        encode_event(&mut self.segment, event);
+        Ok(())
+    }
+
+    /// Returns the event that sets the domain of the record, if
+    /// domains are preserved and the readers would not track it.
+    fn domain_marker(&mut self, record: &Record) -> Option<Event> {
+        let domains = self.domains.as_mut()?;
+        let code = record.event.code;
+
+        // The record itself sets the domain of the CPU
+        if code == (code & TRC_SCHED_TO_RUN) {
+            let extra_0 = record.event.extra[0].unwrap_or(0);
+            domains.insert(record.cpu, Domain::from(extra_0));
+            return None;
+        }
+
+        let tracked = domains
+            .insert(record.cpu, record.domain)
+            .unwrap_or_default();
+        if tracked == record.domain {
+            return None;
+        }
+
+        let mut extra = [None; EVENT_EXTRA_CAPACITY];
+        extra[0] = Some(u32::from(record.domain));
+
+        Some(Event {
+            code: TRC_SCHED_CONTINUE_RUNNING.into(),
+            tsc: record.event.tsc,
+            extra,
+        })
+    }
+
+    fn flush_segment(&mut self) -> Result<()> {
+        let Some(cpu) = self.cpu else {
+            return Ok(());
+        };
+
+        if self.segment.is_empty() {
+            return Ok(());
+        }
+
+        // "segment.len()" never exceeds SEGMENT_LEN, see "write_event"
+        let header = TRC_TRACE_CPU_CHANGE | (2 << 28);
+        let len = self.segment.len() as u32;
+
+        self.wtr
+            .write_ne_u32(header)
+            .and_then(|_| self.wtr.write_ne_u32(cpu))
+            .and_then(|_| self.wtr.write_ne_u32(len))
+            .and_then(|_| self.wtr.write_all(&self.segment))
+            .map_err(|e| Error::io_error("Failed to write trace segment", e))?;
+
+        self.segment.clear();
+        Ok(())
+    }
+}
+
+/// Returns the number of extra values of an event (the leading `Some`s).
+fn extra_len(event: &Event) -> usize {
+    event.extra.iter().take_while(|e| e.is_some()).count()
+}
+
+/// Returns the number of bytes of an encoded event.
+pub(crate) fn encoded_len(event: &Event) -> usize {
+    4 + 8 + extra_len(event) * 4
+}
+
+/// Encodes an event as read by "read_event", always including its tsc value.
+fn encode_event(buf: &mut Vec<u8>, event: &Event) {
+    let len = extra_len(event);
+    debug_assert!(len <= EVENT_EXTRA_CAPACITY);
+
+    let header = (1 << 31) | ((len as u32) << 28) | (event.code.value() & 0x0FFFFFFF);
+
+    // Writing to a "Vec<u8>" cannot fail
+    let _ = buf.write_ne_u32(header);
+    let _ = buf.write_ne_u64(event.tsc);
+    for value in event.extra.iter().map_while(|e| *e) {
+        let _ = buf.write_ne_u32(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TraceWriter;
+    use crate::{
+        record::{Domain, DomainKind},
+        trc::TRC_TRACE_CPU_CHANGE,
+        RecordReader, Trace, TraceBuilder,
+    };
+
+    #[test]
+    fn roundtrip_test() {
+        let bytes = TraceBuilder::new()
+            .cpu(1)
+            .event(10, 0x00081001, &[])
+            .event(20, 0x00081102, &[1, 2, 3])
+            .cpu(0)
+            .event(15, 0x0002800A, &[1, 2, 3, 4, 5, 6, 7])
+            .to_bytes()
+            .unwrap();
+
+        // Two segments: 12 bytes of header each, 12 bytes per event plus the extras
+        assert_eq!(bytes.len(), 2 * 12 + 3 * 12 + 10 * 4);
+
+        let trace = Trace::from_bytes(&bytes).unwrap();
+        assert_eq!(trace.record_count(), 3);
+        assert_eq!(trace[1].cpu(), 0);
+
+        let mut writer = TraceWriter::new(Vec::new());
+        RecordReader::new(bytes.as_slice())
+            .try_for_each(|r| writer.write_record(&r?))
+            .unwrap();
+
+        assert_eq!(writer.finish().unwrap(), bytes);
+    }
+
+    #[test]
+    fn segment_len_test() {
+        let mut builder = TraceBuilder::new();
+        for tsc in 0..10_000 {
+            builder = builder.event(tsc, 0x00081001, &[]);
+        }
+
+        // 120000 bytes of events on a single CPU, in two segments
+        let bytes = builder.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 2 * 12 + 10_000 * 12);
+        assert_eq!(Trace::from_bytes(&bytes).unwrap().record_count(), 10_000);
+    }
+
+    #[test]
+    fn preserve_domains_test() {
+        let trace = TraceBuilder::new()
+            .schedule(5, Domain::new(DomainKind::Guest(1), 0))
+            .event(10, 0x00081001, &[])
+            .event(20, 0x00021101, &[0x00020000])
+            .event(30, 0x00081001, &[])
+            .build()
+            .unwrap();
+
+        // Without the record setting the domain of the first one
+        let mut writer = TraceWriter::new(Vec::new()).preserve_domains(true);
+        writer.write_trace(&trace[1..]).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let written = Trace::from_bytes(&bytes).unwrap();
+        assert_eq!(written.record_count(), 4); // A marker precedes the first record
+        assert_eq!(&written[1..], &trace[1..]);
+    }
+
+    #[test]
+    fn cpu_change_test() {
+        let trace = TraceBuilder::new()
+            .event(0, 0x00081001, &[1])
+            .build()
+            .unwrap();
+
+        let mut record = trace[0].clone();
+        record.event.code = TRC_TRACE_CPU_CHANGE.into();
+
+        let mut writer = TraceWriter::new(Vec::new());
+        assert!(writer.write_record(&record).is_err());
+    }
+}